@@ -1,14 +1,21 @@
 use eframe::{egui, App, Frame, NativeOptions};
 use egui::{
-    plot::{Line, Plot, PlotPoints},
+    plot::{
+        HLine, Legend, Line, LineStyle, MarkerShape, Plot, PlotPoint, PlotPoints, Points, Text,
+        VLine,
+    },
     FontId, Pos2,
 };
 use petgraph::{
-    graph::{DiGraph, NodeIndex},
-    visit::Topo,
+    algo::{tarjan_scc, toposort},
+    graph::{DiGraph, EdgeIndex, NodeIndex},
+    visit::{Dfs, DfsPostOrder, EdgeRef, Reversed},
 };
+use rustfft::{num_complex::Complex, FftPlanner};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io, path::Path, sync::mpsc, thread};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,9 +30,9 @@ impl From<egui::Pos2> for Position {
     }
 }
 
-impl Into<egui::Pos2> for Position {
-    fn into(self) -> egui::Pos2 {
-        egui::pos2(self.x, self.y)
+impl From<Position> for egui::Pos2 {
+    fn from(pos: Position) -> Self {
+        egui::pos2(pos.x, pos.y)
     }
 }
 
@@ -33,334 +40,7367 @@ impl Into<egui::Pos2> for Position {
 enum ComponentType {
     Step,
     TransferFunction,
+    // 1 / (tau s + 1), discretised for the configured step size.
+    LowPass {
+        tau: f32,
+    },
     Scope,
-    Delay(usize),       
+    XYScope,
+    // Shows the latest input value as a number instead of plotting it.
+    NumericDisplay {
+        decimals: usize,
+    },
+    // Caps an output that is deliberately left unused.
+    Terminator,
+    // Outputs `initial` until `steps` inputs have been seen.
+    Delay {
+        steps: usize,
+        initial: f32,
+    },
     Difference,         
-    DiscreteDerivative, 
+    // The raw backward difference, or s / (s/N + 1) when filtered: a
+    // derivative rolled off above N rad/s.
+    Derivative {
+        filtered: bool,
+        n: f32,
+    },
+    // Older derivative blocks, turned into `Derivative` when a model loads.
+    DiscreteDerivative,
+    FilteredDerivative {
+        n: f32,
+    },
     DiscreteIntegrator,
+    // Starts from `initial` and, with a reset threshold, zeroes the
+    // accumulator when the reset port rises through the threshold.
+    IntegratorIC {
+        initial: f32,
+        #[serde(default)]
+        reset: Option<f32>,
+    },
     PIDController {
     
         kp: f32,
         ki: f32,
         kd: f32,
+        #[serde(default)]
+        output_limits: Option<(f32, f32)>,
+    },
+    // Last step's input, starting from `initial`.
+    Memory {
+        initial: f32,
+    },
+    Gain {
+        k: f32,
+    },
+    Sum {
+        signs: Vec<f32>,
+    },
+    Constant {
+        value: f32,
+    },
+    Sine {
+        amplitude: f32,
+        freq_hz: f32,
+        phase: f32,
+    },
+    // Sum of sinusoids, each given as (amplitude, frequency in Hz, phase).
+    MultiSine {
+        components: Vec<(f32, f32, f32)>,
+    },
+    Ramp {
+        slope: f32,
+        start_time: f32,
+    },
+    // Outputs the simulation time t.
+    Clock,
+    Saturation {
+        min: f32,
+        max: f32,
+    },
+    TransferFunctionCoeffs {
+        num: Vec<f32>,
+        den: Vec<f32>,
+    },
+    // num(z) / den(z) in descending powers of z, run as a difference equation.
+    DiscreteTransferFunction {
+        num: Vec<f32>,
+        den: Vec<f32>,
+    },
+    // x' = Ax + Bu, y = Cx + Du with a scalar input and output.
+    StateSpace {
+        a: Vec<Vec<f32>>,
+        b: Vec<f32>,
+        c: Vec<f32>,
+        d: f32,
+    },
+    Product,
+    ZeroOrderHold {
+        sample_every: usize,
+    },
+    Noise {
+        std_dev: f32,
+        seed: u64,
+    },
+    Pulse {
+        amplitude: f32,
+        period: f32,
+        duty: f32,
+    },
+    LookupTable {
+        breakpoints: Vec<f32>,
+        values: Vec<f32>,
+    },
+    RateLimiter {
+        rising: f32,
+        falling: f32,
+    },
+    Switch {
+        threshold: f32,
+    },
+    Abs,
+    Sign,
+    Relay {
+        on_threshold: f32,
+        off_threshold: f32,
+        on_value: f32,
+        off_value: f32,
+    },
+    Quantizer {
+        step: f32,
+    },
+    TransportDelay {
+        seconds: f32,
+    },
+    MinMax {
+        mode: MinOrMax,
+    },
+    ErrorIntegral {
+        mode: ErrorCost,
+    },
+    Subsystem {
+        model: ModelFile,
+    },
+    Inport {
+        index: usize,
+    },
+    Outport {
+        index: usize,
+    },
+    // Bundles `width` scalar inputs into one vector signal.
+    Mux {
+        width: usize,
+    },
+    // Splits a vector signal into `width` scalar outputs.
+    Demux {
+        width: usize,
     },
-    Memory, 
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct Component {
-    id: usize,
-    component_type: ComponentType,
-    position: Position,
-    is_dragging: bool,
+const SUBSYSTEM_INPUTS: [&str; 8] = ["in1", "in2", "in3", "in4", "in5", "in6", "in7", "in8"];
+const SUBSYSTEM_OUTPUTS: [&str; 8] = [
+    "out1", "out2", "out3", "out4", "out5", "out6", "out7", "out8",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum MinOrMax {
+    Min,
+    Max,
 }
 
-struct SimulatorApp {
-    components: HashMap<usize, Component>,
-    connections: DiGraph<usize, f32>,
-    next_id: usize,
-    selected_component: Option<usize>,
-    simulation_data: Vec<f32>, 
+// Integral of |e|, e² or t·|e| over the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum ErrorCost {
+    Iae,
+    Ise,
+    Itae,
 }
 
-impl SimulatorApp {
-    fn new() -> Self {
-        SimulatorApp {
-            components: HashMap::new(),
-            connections: DiGraph::new(),
-            next_id: 0,
-            selected_component: None,
-            simulation_data: vec![],
+impl ErrorCost {
+    fn weight(self, error: f32, t: f32) -> f32 {
+        match self {
+            ErrorCost::Iae => error.abs(),
+            ErrorCost::Ise => error * error,
+            ErrorCost::Itae => t * error.abs(),
         }
     }
 
-    fn add_component(&mut self, component_type: ComponentType, position: egui::Pos2) -> NodeIndex {
-        let id = self.next_id;
-        self.next_id += 1;
-        let component = Component {
-            id,
-            component_type,
-            position: position.into(),
-            is_dragging: false,
-        };
-        self.components.insert(id, component);
-        self.connections.add_node(id)
+    fn name(self) -> &'static str {
+        match self {
+            ErrorCost::Iae => "IAE",
+            ErrorCost::Ise => "ISE",
+            ErrorCost::Itae => "ITAE",
+        }
     }
+}
 
-    fn connect_components(&mut self, from: usize, to: usize) {
-        if let (Some(from_idx), Some(to_idx)) = (
-            self.connections.node_indices().find(|&n| n.index() == from),
-            self.connections.node_indices().find(|&n| n.index() == to),
-        ) {
-            self.connections.add_edge(from_idx, to_idx, 1.0);
+impl ComponentType {
+    fn color(&self) -> egui::Color32 {
+        match self {
+            ComponentType::Step => egui::Color32::LIGHT_BLUE,
+            ComponentType::TransferFunction | ComponentType::LowPass { .. } => {
+                egui::Color32::LIGHT_YELLOW
+            }
+            ComponentType::Scope => egui::Color32::LIGHT_GREEN,
+            ComponentType::XYScope => egui::Color32::from_rgb(160, 230, 200),
+            ComponentType::NumericDisplay { .. } => egui::Color32::from_rgb(190, 235, 190),
+            ComponentType::Terminator => egui::Color32::from_gray(170),
+            ComponentType::Delay { .. } => egui::Color32::KHAKI,
+            ComponentType::Difference => egui::Color32::from_rgb(200, 230, 200),
+            ComponentType::Derivative { .. }
+            | ComponentType::DiscreteDerivative
+            | ComponentType::FilteredDerivative { .. } => egui::Color32::from_rgb(220, 200, 240),
+            ComponentType::DiscreteIntegrator | ComponentType::IntegratorIC { .. } => {
+                egui::Color32::from_rgb(200, 180, 240)
+            }
+            ComponentType::PIDController { .. } => egui::Color32::from_rgb(255, 170, 200),
+            ComponentType::Memory { .. } => egui::Color32::from_rgb(230, 220, 170),
+            ComponentType::Gain { .. } => egui::Color32::GOLD,
+            ComponentType::Sum { .. } => egui::Color32::LIGHT_RED,
+            ComponentType::Constant { .. } => egui::Color32::LIGHT_BLUE,
+            ComponentType::Sine { .. } => egui::Color32::from_rgb(180, 200, 255),
+            ComponentType::MultiSine { .. } => egui::Color32::from_rgb(160, 180, 255),
+            ComponentType::Ramp { .. } => egui::Color32::from_rgb(150, 210, 230),
+            ComponentType::Clock => egui::Color32::from_rgb(170, 220, 220),
+            ComponentType::Saturation { .. } => egui::Color32::from_rgb(255, 190, 120),
+            ComponentType::TransferFunctionCoeffs { .. } => egui::Color32::from_rgb(255, 240, 170),
+            ComponentType::DiscreteTransferFunction { .. } => {
+                egui::Color32::from_rgb(240, 230, 190)
+            }
+            ComponentType::StateSpace { .. } => egui::Color32::from_rgb(255, 225, 150),
+            ComponentType::Product => egui::Color32::from_rgb(255, 160, 160),
+            ComponentType::ZeroOrderHold { .. } => egui::Color32::from_rgb(190, 220, 190),
+            ComponentType::Noise { .. } => egui::Color32::from_rgb(200, 200, 200),
+            ComponentType::Pulse { .. } => egui::Color32::from_rgb(160, 190, 255),
+            ComponentType::LookupTable { .. } => egui::Color32::from_rgb(240, 210, 160),
+            ComponentType::RateLimiter { .. } => egui::Color32::from_rgb(255, 210, 140),
+            ComponentType::Switch { .. } => egui::Color32::from_rgb(210, 210, 255),
+            ComponentType::Abs => egui::Color32::from_rgb(200, 230, 255),
+            ComponentType::Sign => egui::Color32::from_rgb(230, 200, 255),
+            ComponentType::Relay { .. } => egui::Color32::from_rgb(255, 200, 200),
+            ComponentType::Quantizer { .. } => egui::Color32::from_rgb(200, 255, 220),
+            ComponentType::TransportDelay { .. } => egui::Color32::KHAKI,
+            ComponentType::MinMax { mode } => match mode {
+                MinOrMax::Min => egui::Color32::from_rgb(170, 220, 255),
+                MinOrMax::Max => egui::Color32::from_rgb(255, 200, 170),
+            },
+            ComponentType::ErrorIntegral { .. } => egui::Color32::from_rgb(240, 190, 220),
+            ComponentType::Subsystem { .. } => egui::Color32::from_rgb(180, 180, 220),
+            ComponentType::Inport { .. } | ComponentType::Outport { .. } => {
+                egui::Color32::from_rgb(220, 220, 220)
+            }
+            ComponentType::Mux { .. } | ComponentType::Demux { .. } => {
+                egui::Color32::from_rgb(120, 120, 120)
+            }
         }
     }
 
+    fn label(&self) -> String {
+        match self {
+            ComponentType::Step => "Step".to_string(),
+            ComponentType::TransferFunction => "1 / (s + 1)".to_string(),
+            ComponentType::LowPass { tau } => format!("1 / ({}s + 1)", tau),
+            ComponentType::Scope => "Scope".to_string(),
+            ComponentType::XYScope => "XY Scope".to_string(),
+            ComponentType::NumericDisplay { .. } => "123".to_string(),
+            ComponentType::Terminator => "⏚".to_string(),
+            ComponentType::Delay { steps, .. } => format!("z^-{}", steps),
+            ComponentType::Difference => "1 - z^-1".to_string(),
+            ComponentType::Derivative {
+                filtered: false, ..
+            }
+            | ComponentType::DiscreteDerivative => "d/dt".to_string(),
+            ComponentType::Derivative { n, .. } | ComponentType::FilteredDerivative { n } => {
+                format!("d/dt N={}", n)
+            }
+            ComponentType::DiscreteIntegrator => "∫".to_string(),
+            ComponentType::IntegratorIC { initial, .. } => format!("∫ x0={}", initial),
+            ComponentType::PIDController { .. } => "PID".to_string(),
+            ComponentType::Memory { .. } => "Memory".to_string(),
+            ComponentType::Gain { k } => format!("×{}", k),
+            ComponentType::Constant { value } => format!("{}", value),
+            ComponentType::Sine { .. } => "sin".to_string(),
+            ComponentType::MultiSine { components } => format!("Σ sin ×{}", components.len()),
+            ComponentType::Ramp { slope, .. } => format!("Ramp {}", slope),
+            ComponentType::Clock => "⏱ t".to_string(),
+            ComponentType::Saturation { min, max } => format!("Sat [{}, {}]", min, max),
+            ComponentType::Sum { signs } => format!(
+                "Σ {}",
+                signs
+                    .iter()
+                    .map(|sign| if *sign < 0.0 { '−' } else { '+' })
+                    .collect::<String>()
+            ),
+            ComponentType::TransferFunctionCoeffs { den, .. } => {
+                format!("H(s) n={}", den.len().saturating_sub(1))
+            }
+            ComponentType::DiscreteTransferFunction { den, .. } => {
+                format!("H(z) n={}", den.len().saturating_sub(1))
+            }
+            ComponentType::StateSpace { a, .. } => format!("SS n={}", a.len()),
+            ComponentType::Product => "Π".to_string(),
+            ComponentType::ZeroOrderHold { sample_every } => format!("ZOH /{}", sample_every),
+            ComponentType::Noise { std_dev, .. } => format!("Noise σ={}", std_dev),
+            ComponentType::Pulse { period, .. } => format!("Pulse T={}", period),
+            ComponentType::LookupTable { breakpoints, .. } => {
+                format!("LUT {}", breakpoints.len())
+            }
+            ComponentType::RateLimiter { .. } => "Rate lim".to_string(),
+            ComponentType::Switch { threshold } => format!("Switch ≥{}", threshold),
+            ComponentType::Abs => "|u|".to_string(),
+            ComponentType::Sign => "sgn".to_string(),
+            ComponentType::Relay { .. } => "Relay".to_string(),
+            ComponentType::Quantizer { step } => format!("Quant {}", step),
+            ComponentType::TransportDelay { seconds } => format!("e^-{}s", seconds),
+            ComponentType::MinMax { mode } => format!("{:?}", mode),
+            ComponentType::ErrorIntegral { mode } => mode.name().to_string(),
+            ComponentType::Subsystem { .. } => "Subsystem".to_string(),
+            ComponentType::Inport { index } => format!("In {}", index + 1),
+            ComponentType::Outport { index } => format!("Out {}", index + 1),
+            ComponentType::Mux { width } => format!("Mux {}", width),
+            ComponentType::Demux { width } => format!("Demux {}", width),
+        }
+    }
 
-    fn simulate(&mut self) {
-      
-        self.simulation_data.clear();
-
-        let time_step = 0.1;
-        let steps = 100;
-        let mut component_outputs = HashMap::new();
+    // Block type and its settings, one per line, for the canvas tooltip.
+    fn describe(&self) -> String {
+        let (name, params): (&str, Vec<String>) = match self {
+            ComponentType::Step => ("Step", vec![]),
+            ComponentType::TransferFunction => ("Transfer function", vec!["alpha = 0.1".into()]),
+            ComponentType::LowPass { tau } => ("Low-pass", vec![format!("tau = {} s", tau)]),
+            ComponentType::Scope => ("Scope", vec![]),
+            ComponentType::XYScope => ("XY scope", vec![]),
+            ComponentType::NumericDisplay { decimals } => {
+                ("Display", vec![format!("{} decimals", decimals)])
+            }
+            ComponentType::Terminator => ("Terminator", vec![]),
+            ComponentType::Delay { steps, initial } => (
+                "Delay",
+                vec![format!("{} steps", steps), format!("initial = {}", initial)],
+            ),
+            ComponentType::Difference => ("Difference", vec![]),
+            ComponentType::Derivative {
+                filtered: false, ..
+            }
+            | ComponentType::DiscreteDerivative => ("Derivative", vec!["raw".to_string()]),
+            ComponentType::Derivative { n, .. } | ComponentType::FilteredDerivative { n } => {
+                ("Derivative", vec![format!("filtered, N = {} rad/s", n)])
+            }
+            ComponentType::DiscreteIntegrator => ("Integrator", vec![]),
+            ComponentType::IntegratorIC { initial, reset } => {
+                let mut params = vec![format!("x0 = {}", initial)];
+                if let Some(threshold) = reset {
+                    params.push(format!("reset at {}", threshold));
+                }
+                ("Integrator", params)
+            }
+            ComponentType::PIDController {
+                kp,
+                ki,
+                kd,
+                output_limits,
+            } => {
+                let mut params = vec![
+                    format!("Kp = {}", kp),
+                    format!("Ki = {}", ki),
+                    format!("Kd = {}", kd),
+                ];
+                if let Some((min, max)) = output_limits {
+                    params.push(format!("limits [{}, {}]", min, max));
+                }
+                ("PID controller", params)
+            }
+            ComponentType::Memory { initial } => ("Memory", vec![format!("initial = {}", initial)]),
+            ComponentType::Gain { k } => ("Gain", vec![format!("k = {}", k)]),
+            ComponentType::Sum { signs } => ("Sum", vec![format!("signs {:?}", signs)]),
+            ComponentType::Constant { value } => ("Constant", vec![format!("value = {}", value)]),
+            ComponentType::Sine {
+                amplitude,
+                freq_hz,
+                phase,
+            } => (
+                "Sine",
+                vec![
+                    format!("amplitude = {}", amplitude),
+                    format!("frequency = {} Hz", freq_hz),
+                    format!("phase = {} rad", phase),
+                ],
+            ),
+            ComponentType::MultiSine { components } => (
+                "Multi-sine",
+                components
+                    .iter()
+                    .map(|(amplitude, freq_hz, phase)| {
+                        format!("{} sin(2π·{} Hz·t + {})", amplitude, freq_hz, phase)
+                    })
+                    .collect(),
+            ),
+            ComponentType::Ramp { slope, start_time } => (
+                "Ramp",
+                vec![
+                    format!("slope = {}", slope),
+                    format!("start = {} s", start_time),
+                ],
+            ),
+            ComponentType::Clock => ("Clock", vec!["y = t".to_string()]),
+            ComponentType::Saturation { min, max } => {
+                ("Saturation", vec![format!("[{}, {}]", min, max)])
+            }
+            ComponentType::TransferFunctionCoeffs { num, den } => (
+                "Transfer function",
+                vec![format!("num {:?}", num), format!("den {:?}", den)],
+            ),
+            ComponentType::DiscreteTransferFunction { num, den } => (
+                "Discrete transfer function",
+                vec![format!("num {:?}", num), format!("den {:?}", den)],
+            ),
+            ComponentType::StateSpace { a, b, c, d } => (
+                "State space",
+                vec![
+                    format!("A {:?}", a),
+                    format!("B {:?}", b),
+                    format!("C {:?}", c),
+                    format!("D = {}", d),
+                ],
+            ),
+            ComponentType::Product => ("Product", vec![]),
+            ComponentType::ZeroOrderHold { sample_every } => (
+                "Zero-order hold",
+                vec![format!("every {} steps", sample_every)],
+            ),
+            ComponentType::Noise { std_dev, seed } => (
+                "Noise",
+                vec![format!("σ = {}", std_dev), format!("seed = {}", seed)],
+            ),
+            ComponentType::Pulse {
+                amplitude,
+                period,
+                duty,
+            } => (
+                "Pulse",
+                vec![
+                    format!("amplitude = {}", amplitude),
+                    format!("period = {} s", period),
+                    format!("duty = {}", duty),
+                ],
+            ),
+            ComponentType::LookupTable {
+                breakpoints,
+                values,
+            } => (
+                "Lookup table",
+                vec![
+                    format!("breakpoints {:?}", breakpoints),
+                    format!("values {:?}", values),
+                ],
+            ),
+            ComponentType::RateLimiter { rising, falling } => (
+                "Rate limiter",
+                vec![
+                    format!("rising = {}/s", rising),
+                    format!("falling = {}/s", falling),
+                ],
+            ),
+            ComponentType::Switch { threshold } => {
+                ("Switch", vec![format!("threshold = {}", threshold)])
+            }
+            ComponentType::Abs => ("Abs", vec![]),
+            ComponentType::Sign => ("Sign", vec![]),
+            ComponentType::Relay {
+                on_threshold,
+                off_threshold,
+                on_value,
+                off_value,
+            } => (
+                "Relay",
+                vec![
+                    format!("on above {} → {}", on_threshold, on_value),
+                    format!("off below {} → {}", off_threshold, off_value),
+                ],
+            ),
+            ComponentType::Quantizer { step } => ("Quantizer", vec![format!("step = {}", step)]),
+            ComponentType::TransportDelay { seconds } => {
+                ("Transport delay", vec![format!("{} s", seconds)])
+            }
+            ComponentType::MinMax { mode } => ("Min/Max", vec![format!("{:?}", mode)]),
+            ComponentType::ErrorIntegral { mode } => {
+                ("Error integral", vec![mode.name().to_string()])
+            }
+            ComponentType::Subsystem { model } => {
+                let (inputs, outputs) = model.boundary_ports();
+                (
+                    "Subsystem",
+                    vec![
+                        format!("{} blocks", model.components.len()),
+                        format!("{} in, {} out", inputs, outputs),
+                    ],
+                )
+            }
+            ComponentType::Inport { index } => ("Inport", vec![format!("port {}", index + 1)]),
+            ComponentType::Outport { index } => ("Outport", vec![format!("port {}", index + 1)]),
+            ComponentType::Mux { width } => ("Mux", vec![format!("width = {}", width)]),
+            ComponentType::Demux { width } => ("Demux", vec![format!("width = {}", width)]),
+        };
+        let mut lines = vec![name.to_string()];
+        lines.extend(params);
+        lines.join("\n")
+    }
 
-        for step in 0..steps {
-            println!("Simulation step {}", step);
+    fn input_ports(&self) -> Vec<&'static str> {
+        match self {
+            ComponentType::Step
+            | ComponentType::Constant { .. }
+            | ComponentType::Sine { .. }
+            | ComponentType::MultiSine { .. }
+            | ComponentType::Ramp { .. }
+            | ComponentType::Clock
+            | ComponentType::Noise { .. }
+            | ComponentType::Pulse { .. }
+            | ComponentType::Inport { .. } => vec![],
+            ComponentType::Subsystem { model } => {
+                SUBSYSTEM_INPUTS[..model.boundary_ports().0].to_vec()
+            }
+            ComponentType::Mux { width } => {
+                SUBSYSTEM_INPUTS[..(*width).min(SUBSYSTEM_INPUTS.len())].to_vec()
+            }
+            // One port per sign.
+            ComponentType::Sum { signs } => {
+                SUBSYSTEM_INPUTS[..signs.len().clamp(1, SUBSYSTEM_INPUTS.len())].to_vec()
+            }
+            ComponentType::PIDController { .. } => vec!["setpoint", "measurement"],
+            ComponentType::Switch { .. } => vec!["in1", "control", "in2"],
+            ComponentType::XYScope => vec!["x", "y"],
+            ComponentType::IntegratorIC { reset: Some(_), .. } => vec!["in", "reset"],
+            _ => vec!["in"],
+        }
+    }
 
-            let mut topo = Topo::new(&self.connections);
-            while let Some(node_idx) = topo.next(&self.connections) {
-                let component_id = self.connections[node_idx];
-
-                if let Some(component) = self.components.get(&component_id) {
-                    let output = match &component.component_type {
-                        ComponentType::Step => 1.0,
-                        ComponentType::TransferFunction => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            let prev_output = *component_outputs.get(&component_id).unwrap_or(&0.0);
-                            let alpha = 0.1;
-                            prev_output + alpha * (input_value - prev_output)
-                        }
-                        ComponentType::Scope => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            self.simulation_data.push(input_value);
-                            continue;
-                        }
-                        ComponentType::Delay(delay_steps) => {
-                            todo!()
-                        }
-                        ComponentType::Difference => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            let prev_value =
-                                *component_outputs.get(&component_id).unwrap_or(&input_value);
-                            input_value - prev_value
-                        }
-                        ComponentType::DiscreteDerivative => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            let prev_value =
-                                *component_outputs.get(&component_id).unwrap_or(&input_value);
-                            (input_value - prev_value) / time_step
-                        }
-                        ComponentType::DiscreteIntegrator => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            let prev_value = *component_outputs.get(&component_id).unwrap_or(&0.0);
-                            prev_value + input_value * time_step
-                        }
-                        ComponentType::PIDController { kp, ki, kd } => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            let prev_error =
-                                *component_outputs.get(&(component_id + 1)).unwrap_or(&0.0);
-                            let prev_integral =
-                                *component_outputs.get(&(component_id + 2)).unwrap_or(&0.0);
-                            let error = 1.0 - input_value; 
-                            let integral = prev_integral + error * time_step;
-                            let derivative = (error - prev_error) / time_step;
-                            *kp * error + *ki * integral + *kd * derivative
-                        }
-                        ComponentType::Memory => {
-                            let input_value =
-                                self.get_input_value(component_id, &component_outputs);
-                            *component_outputs.get(&component_id).unwrap_or(&input_value)
-                        }
-                    };
+    fn output_ports(&self) -> Vec<&'static str> {
+        match self {
+            ComponentType::Scope
+            | ComponentType::XYScope
+            | ComponentType::NumericDisplay { .. }
+            | ComponentType::Terminator
+            | ComponentType::Outport { .. } => vec![],
+            ComponentType::Subsystem { model } => {
+                SUBSYSTEM_OUTPUTS[..model.boundary_ports().1].to_vec()
+            }
+            ComponentType::Demux { width } => {
+                SUBSYSTEM_OUTPUTS[..(*width).min(SUBSYSTEM_OUTPUTS.len())].to_vec()
+            }
+            _ => vec!["out"],
+        }
+    }
 
-                    component_outputs.insert(component_id, output);
-                    println!(
-                        "Component ID {} ({:?}) output: {}",
-                        component_id, component.component_type, output
-                    );
-                }
+    // Maps blocks from older model files onto their current variants.
+    fn upgrade_legacy(&mut self) {
+        match self {
+            ComponentType::DiscreteDerivative => {
+                *self = ComponentType::Derivative {
+                    filtered: false,
+                    n: 10.0,
+                };
+            }
+            ComponentType::FilteredDerivative { n } => {
+                let n = *n;
+                *self = ComponentType::Derivative { filtered: true, n };
             }
+            _ => {}
         }
     }
-    fn add_delay(&mut self, delay_steps: usize, position: egui::Pos2) {
-        let component_type = ComponentType::Delay(delay_steps);
-        self.add_component(component_type, position);
+
+    // The scalar parameters a sweep can vary, by name.
+    fn parameters_mut(&mut self) -> Vec<(&'static str, &mut f32)> {
+        match self {
+            ComponentType::LowPass { tau } => vec![("tau", tau)],
+            ComponentType::Derivative { n, .. } => vec![("N", n)],
+            ComponentType::IntegratorIC { initial, .. } => vec![("x0", initial)],
+            ComponentType::PIDController { kp, ki, kd, .. } => {
+                vec![("kp", kp), ("ki", ki), ("kd", kd)]
+            }
+            ComponentType::Gain { k } => vec![("k", k)],
+            ComponentType::Constant { value } => vec![("value", value)],
+            ComponentType::Sine {
+                amplitude,
+                freq_hz,
+                phase,
+            } => vec![
+                ("amplitude", amplitude),
+                ("frequency", freq_hz),
+                ("phase", phase),
+            ],
+            ComponentType::Ramp { slope, start_time } => {
+                vec![("slope", slope), ("start time", start_time)]
+            }
+            ComponentType::Saturation { min, max } => vec![("min", min), ("max", max)],
+            ComponentType::StateSpace { d, .. } => vec![("D", d)],
+            ComponentType::Noise { std_dev, .. } => vec![("std dev", std_dev)],
+            ComponentType::Pulse {
+                amplitude,
+                period,
+                duty,
+            } => vec![("amplitude", amplitude), ("period", period), ("duty", duty)],
+            ComponentType::RateLimiter { rising, falling } => {
+                vec![("rising", rising), ("falling", falling)]
+            }
+            ComponentType::Switch { threshold } => vec![("threshold", threshold)],
+            ComponentType::Relay {
+                on_threshold,
+                off_threshold,
+                on_value,
+                off_value,
+            } => vec![
+                ("on threshold", on_threshold),
+                ("off threshold", off_threshold),
+                ("on value", on_value),
+                ("off value", off_value),
+            ],
+            ComponentType::Quantizer { step } => vec![("step", step)],
+            ComponentType::TransportDelay { seconds } => vec![("delay", seconds)],
+            _ => vec![],
+        }
     }
 
-    fn add_difference(&mut self, position: egui::Pos2) {
-        self.add_component(ComponentType::Difference, position);
+    // Highest frequency a periodic source produces, in Hz. A pulse counts
+    // only its fundamental.
+    fn source_frequency(&self) -> Option<f32> {
+        match self {
+            ComponentType::Sine { freq_hz, .. } => Some(freq_hz.abs()),
+            ComponentType::MultiSine { components } => components
+                .iter()
+                .map(|(_, freq_hz, _)| freq_hz.abs())
+                .reduce(f32::max),
+            ComponentType::Pulse { period, .. } if *period > 0.0 => Some(1.0 / period),
+            _ => None,
+        }
     }
 
-    fn add_discrete_derivative(&mut self, position: egui::Pos2) {
-        self.add_component(ComponentType::DiscreteDerivative, position);
+    // Blocks that only consume their inputs.
+    fn is_sink(&self) -> bool {
+        matches!(
+            self,
+            ComponentType::Scope
+                | ComponentType::XYScope
+                | ComponentType::NumericDisplay { .. }
+                | ComponentType::Terminator
+        )
     }
 
-    fn add_discrete_integrator(&mut self, position: egui::Pos2) {
-        self.add_component(ComponentType::DiscreteIntegrator, position);
+    // Blocks whose output only depends on stored state: they are evaluated
+    // before their inputs and latch the new input at the end of each step.
+    fn breaks_algebraic_loop(&self) -> bool {
+        match self {
+            ComponentType::Delay { steps, .. } => *steps > 0,
+            ComponentType::Memory { .. }
+            | ComponentType::DiscreteIntegrator
+            | ComponentType::IntegratorIC { .. }
+            | ComponentType::ErrorIntegral { .. }
+            | ComponentType::TransportDelay { .. } => true,
+            _ => false,
+        }
     }
+}
 
-    fn add_pid_controller(&mut self, kp: f32, ki: f32, kd: f32, position: egui::Pos2) {
-        let component_type = ComponentType::PIDController { kp, ki, kd };
-        self.add_component(component_type, position);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Component {
+    id: usize,
+    #[serde(deserialize_with = "deserialize_component_type")]
+    component_type: ComponentType,
+    position: Position,
+    is_dragging: bool,
+    #[serde(default)]
+    name: Option<String>,
+    // A Sum spliced into a wire to add a disturbance, drawn as a small marker.
+    #[serde(default)]
+    injection_point: bool,
+    // Disabled blocks are bypassed by the simulation and drawn greyed out.
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+// Reads a block, also accepting the `{"Delay": steps}` and `"Memory"` shapes
+// written before those blocks had an initial value. Binary models are
+// versioned and always hold the current shape.
+fn deserialize_component_type<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<ComponentType, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Current(ComponentType),
+        Old(OldComponentType),
+    }
+    #[derive(Deserialize)]
+    enum OldComponentType {
+        Delay(usize),
+        Memory,
     }
 
-    fn add_memory(&mut self, position: egui::Pos2) {
-        self.add_component(ComponentType::Memory, position);
+    if !deserializer.is_human_readable() {
+        return ComponentType::deserialize(deserializer);
     }
+    Ok(match Stored::deserialize(deserializer)? {
+        Stored::Current(component_type) => component_type,
+        Stored::Old(OldComponentType::Delay(steps)) => ComponentType::Delay {
+            steps,
+            initial: 0.0,
+        },
+        Stored::Old(OldComponentType::Memory) => ComponentType::Memory { initial: 0.0 },
+    })
+}
 
-    fn get_input_value(&self, component_id: usize, component_outputs: &HashMap<usize, f32>) -> f32 {
-        let mut input_sum = 0.0;
+#[derive(Debug, Clone, PartialEq)]
+enum SimError {
+    AlgebraicLoop(Vec<usize>),
+    EmptyGraph,
+    MissingInput {
+        component: usize,
+        port: &'static str,
+    },
+    InvalidTimeStep(f32),
+    // An output left the divergence bound or stopped being finite.
+    Diverged {
+        component: usize,
+        step: usize,
+        value: f32,
+    },
+}
 
-        
-        if let Some(node_idx) = self
-            .connections
-            .node_indices()
-            .find(|n| self.connections[*n] == component_id)
-        {
-            for neighbor in self
-                .connections
-                .neighbors_directed(node_idx, petgraph::Incoming)
-            {
-                if let Some(&output_value) = component_outputs.get(&self.connections[neighbor]) {
-                    input_sum += output_value;
-                }
+impl fmt::Display for SimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimError::AlgebraicLoop(ids) => write!(
+                f,
+                "Algebraic loop without a delay or integrator through components {:?}",
+                ids
+            ),
+            SimError::EmptyGraph => write!(f, "The model has no components to simulate"),
+            SimError::MissingInput { component, port } => write!(
+                f,
+                "Component {} has nothing connected to input `{}`",
+                component, port
+            ),
+            SimError::InvalidTimeStep(dt) => {
+                write!(f, "Time step must be positive and finite, got {}", dt)
             }
+            SimError::Diverged {
+                component,
+                step,
+                value,
+            } => write!(
+                f,
+                "Simulation diverged: component {} output {} at step {}",
+                component, value, step
+            ),
         }
+    }
+}
 
-        println!(
-            "Component ID {} received input value: {}",
-            component_id, input_sum
-        ); 
+// The value on a wire. Only Mux produces vectors; blocks that expect a
+// scalar read a vector as its first element.
+#[derive(Debug, Clone, PartialEq)]
+enum Signal {
+    Scalar(f32),
+    Vector(Vec<f32>),
+}
 
-        input_sum
+impl Signal {
+    fn scalar(&self) -> f32 {
+        self.element(0)
     }
-}
 
-impl App for SimulatorApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-    
-        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                if ui.button("Add Step").clicked() {
-                    self.add_component(ComponentType::Step, Pos2::new(50.0, 100.0));
-                }
-                if ui.button("Add Transfer Function").clicked() {
-                    self.add_component(ComponentType::TransferFunction, Pos2::new(150.0, 100.0));
-                }
-                if ui.button("Add Scope").clicked() {
-                    self.add_component(ComponentType::Scope, Pos2::new(250.0, 100.0));
-                }
-                if ui.button("Run Simulation").clicked() {
-                    self.simulate();
-                }
-            });
-        });
+    fn element(&self, index: usize) -> f32 {
+        match self {
+            Signal::Scalar(value) if index == 0 => *value,
+            Signal::Scalar(_) => 0.0,
+            Signal::Vector(values) => values.get(index).copied().unwrap_or(0.0),
+        }
+    }
 
-      
-        egui::SidePanel::left("side_panel").show(ctx, |ui| {
-            ui.heading("Simulation Output");
+    fn scaled(&self, gain: f32) -> Signal {
+        match self {
+            Signal::Scalar(value) => Signal::Scalar(value * gain),
+            Signal::Vector(values) => Signal::Vector(values.iter().map(|v| v * gain).collect()),
+        }
+    }
 
-        
-            let plot_points: PlotPoints = PlotPoints::from_iter(
-                self.simulation_data
-                    .iter()
-                    .enumerate()
-                    .map(|(i, &value)| [i as f64 * 0.1, value as f64]), 
-            );
+    fn len(&self) -> usize {
+        match self {
+            Signal::Scalar(_) => 1,
+            Signal::Vector(values) => values.len(),
+        }
+    }
 
-         
-            let line = Line::new(plot_points).name("Simulation Result");
+    fn values(&self) -> &[f32] {
+        match self {
+            Signal::Scalar(value) => std::slice::from_ref(value),
+            Signal::Vector(values) => values,
+        }
+    }
 
-            Plot::new("Scope Plot")
-                .view_aspect(2.0) 
-                .show(ui, |plot_ui| {
-                    plot_ui.line(line);
-                });
-        });
+    // Element-wise sum; a scalar adds to the first element of a vector.
+    fn add(&self, other: &Signal) -> Signal {
+        match (self, other) {
+            (Signal::Scalar(a), Signal::Scalar(b)) => Signal::Scalar(a + b),
+            _ => Signal::Vector(
+                (0..self.len().max(other.len()))
+                    .map(|i| self.element(i) + other.element(i))
+                    .collect(),
+            ),
+        }
+    }
+}
 
-     
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let painter = ui.painter();
-            let mut connection_to_create = None; 
+// A simulation in progress. Play mode keeps one across frames and steps it
+// a little each frame.
+struct Run {
+    order: Vec<NodeIndex>,
+    step: usize,
+    steps: usize,
+    // Keyed by component id and output port.
+    outputs: HashMap<(usize, usize), Signal>,
+    // Sinks with nothing wired in, which the run skips.
+    unconnected_sinks: HashSet<NodeIndex>,
+}
 
-        
-            for edge in self.connections.edge_indices() {
-                let (from, to) = self.connections.edge_endpoints(edge).unwrap();
-                let from_pos: Pos2 = self.components[&self.connections[from]]
-                    .position
-                    .clone()
-                    .into();
-                let to_pos: Pos2 = self.components[&self.connections[to]]
-                    .position
-                    .clone()
-                    .into();
-                painter.line_segment([from_pos, to_pos], (1.0, egui::Color32::LIGHT_GRAY));
-            }
+impl Run {
+    fn finished(&self) -> bool {
+        self.step >= self.steps
+    }
+}
 
-        
-            for (id, component) in self.components.iter_mut() {
-                let pos: Pos2 = component.position.clone().into();
-                let rect = egui::Rect::from_center_size(pos, egui::vec2(80.0, 40.0));
-
-                let color = match component.component_type {
-                    ComponentType::Step => egui::Color32::LIGHT_BLUE,
-                    ComponentType::TransferFunction => egui::Color32::LIGHT_YELLOW,
-                    ComponentType::Scope => egui::Color32::LIGHT_GREEN,
-                    _ => todo!(),
-                };
+// Fails on the first of `component`'s `ports` outputs that is not finite or,
+// with a bound set, has grown past it.
+fn check_divergence(
+    outputs: &HashMap<(usize, usize), Signal>,
+    component: usize,
+    ports: usize,
+    step: usize,
+    bound: Option<f32>,
+) -> Result<(), SimError> {
+    let diverged = |value: f32| !value.is_finite() || bound.is_some_and(|b| value.abs() > b);
+    for port in 0..ports {
+        let values = outputs
+            .get(&(component, port))
+            .map_or(&[][..], Signal::values);
+        if let Some(&value) = values.iter().find(|value| diverged(**value)) {
+            return Err(SimError::Diverged {
+                component,
+                step,
+                value,
+            });
+        }
+    }
+    Ok(())
+}
 
-              
-                painter.rect_filled(rect, 5.0, color);
-                let label = match component.component_type {
-                    ComponentType::Step => "Step",
-                    ComponentType::TransferFunction => "1 / (s + 1)",
-                    ComponentType::Scope => "Scope",
-                    _ => todo!(),
-                };
-                painter.text(
-                    pos,
-                    egui::Align2::CENTER_CENTER,
-                    label,
-                    FontId::default(),
-                    egui::Color32::BLACK,
-                );
+#[derive(Debug, Clone, PartialEq)]
+enum ValidationError {
+    UnconnectedInput {
+        component: usize,
+        port: &'static str,
+    },
+    DanglingOutput {
+        component: usize,
+    },
+    // A wire on a port index the block does not have.
+    NoSuchPort {
+        component: usize,
+        kind: PortKind,
+        port: usize,
+    },
+    // A sink with nothing wired in; it is skipped rather than recording zeros.
+    UnconnectedSink {
+        component: usize,
+    },
+    // A zero-order hold sampling slower than twice the highest source
+    // frequency feeding it, so that frequency aliases.
+    Undersampled {
+        component: usize,
+        sample_hz: f32,
+        signal_hz: f32,
+    },
+    // A zero-order hold whose sample period is longer than the run.
+    SamplePeriodTooLong {
+        component: usize,
+    },
+    AlgebraicLoop(Vec<usize>),
+    InvalidSubsystem {
+        component: usize,
+        error: Box<ValidationError>,
+    },
+}
 
-                // Handle dragging
-                if ui.rect_contains_pointer(rect) && ui.input().pointer.any_pressed() {
-                    component.is_dragging = true;
-                }
-                if ui.input().pointer.any_released() {
-                    component.is_dragging = false;
-                }
-                if component.is_dragging {
-                    if let Some(mouse_pos) = ui.input().pointer.hover_pos() {
-                        component.position = mouse_pos.into();
-                    }
-                }
+impl ValidationError {
+    // Dangling outputs, idle sinks and sampling advice are harmless;
+    // everything else produces misleading results.
+    fn blocks_run(&self) -> bool {
+        !matches!(
+            self,
+            ValidationError::DanglingOutput { .. }
+                | ValidationError::UnconnectedSink { .. }
+                | ValidationError::Undersampled { .. }
+                | ValidationError::SamplePeriodTooLong { .. }
+        )
+    }
 
-        
-                if ui.rect_contains_pointer(rect) && ui.input().pointer.any_click() {
-                    if let Some(start_id) = self.selected_component {
-                        if start_id != *id {
-                            connection_to_create = Some((start_id, *id));
-                        }
-                        self.selected_component = None;
-                    } else {
-                        self.selected_component = Some(*id);
-                    }
+    fn components(&self) -> Vec<usize> {
+        match self {
+            ValidationError::UnconnectedInput { component, .. }
+            | ValidationError::DanglingOutput { component }
+            | ValidationError::UnconnectedSink { component }
+            | ValidationError::NoSuchPort { component, .. }
+            | ValidationError::Undersampled { component, .. }
+            | ValidationError::SamplePeriodTooLong { component }
+            | ValidationError::InvalidSubsystem { component, .. } => vec![*component],
+            ValidationError::AlgebraicLoop(ids) => ids.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::UnconnectedInput { component, port } => write!(
+                f,
+                "{}",
+                SimError::MissingInput {
+                    component: *component,
+                    port
                 }
+            ),
+            ValidationError::DanglingOutput { component } => {
+                write!(f, "Output of component {} is not connected", component)
             }
-
-         
-            if let Some((start_id, end_id)) = connection_to_create {
-                self.connect_components(start_id, end_id);
+            ValidationError::NoSuchPort {
+                component,
+                kind,
+                port,
+            } => {
+                let kind = match kind {
+                    PortKind::Input => "input",
+                    PortKind::Output => "output",
+                };
+                write!(
+                    f,
+                    "Component {} has a wire on {} port {}, which it does not have",
+                    component,
+                    kind,
+                    port + 1
+                )
             }
-        });
+            ValidationError::UnconnectedSink { component } => write!(
+                f,
+                "Sink component {} has no input and is skipped",
+                component
+            ),
+            ValidationError::Undersampled {
+                component,
+                sample_hz,
+                signal_hz,
+            } => write!(
+                f,
+                "Hold {} samples at {} Hz but its input has content at {} Hz, \
+                 near or above the {} Hz Nyquist limit",
+                component,
+                sample_hz,
+                signal_hz,
+                sample_hz / 2.0
+            ),
+            ValidationError::SamplePeriodTooLong { component } => write!(
+                f,
+                "Hold {} samples less than once per run and will only hold its first input",
+                component
+            ),
+            ValidationError::AlgebraicLoop(ids) => {
+                write!(f, "{}", SimError::AlgebraicLoop(ids.clone()))
+            }
+            ValidationError::InvalidSubsystem { component, error } => {
+                write!(f, "Inside subsystem {}: {}", component, error)
+            }
+        }
     }
 }
 
-fn main() {
-    let options = NativeOptions::default();
-    eframe::run_native(
-        " Simulator",
-        options,
-        Box::new(|_cc| Box::new(SimulatorApp::new())),
-    );
+#[derive(Debug)]
+enum ComponentState {
+    Stateless,
+    Filter {
+        output: f32,
+    },
+    PreviousInput(Option<f32>),
+    PreviousOutput(f32),
+    Derivative {
+        prev_input: Option<f32>,
+        output: f32,
+    },
+    Relay {
+        on: bool,
+    },
+    Integrator {
+        accumulator: f32,
+        prev_input: Option<f32>,
+        prev_reset: Option<f32>,
+    },
+    Pid {
+        prev_error: f32,
+        integral: f32,
+    },
+    Delay {
+        buffer: VecDeque<f32>,
+    },
+    Hold {
+        value: f32,
+    },
+    // Past inputs with the most recent at the back, and the delay in samples.
+    TransportDelay {
+        buffer: VecDeque<f32>,
+        samples: f32,
+    },
+    LinearFilter {
+        b: Vec<f32>,
+        a: Vec<f32>,
+        inputs: VecDeque<f32>,
+        outputs: VecDeque<f32>,
+    },
+    StateSpace {
+        a: Vec<Vec<f32>>,
+        b: Vec<f32>,
+        c: Vec<f32>,
+        d: f32,
+        x: Vec<f32>,
+        prev_input: Option<f32>,
+    },
+    Noise(Rng),
+    Subsystem(Box<SubsystemRun>),
+}
+
+// The inner model of a subsystem block, stepped once per outer step.
+struct SubsystemRun {
+    app: SimulatorApp,
+    run: Run,
+}
+
+impl fmt::Debug for SubsystemRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SubsystemRun")
+            .field("components", &self.app.components.len())
+            .field("step", &self.run.step)
+            .finish()
+    }
+}
+
+impl SubsystemRun {
+    fn new(model: &ModelFile, dt: f32, solver: SolverMethod, duration: f32) -> Self {
+        let mut app = SimulatorApp::new();
+        app.load_model(model.clone());
+        app.dt = dt;
+        app.solver = solver;
+        app.total_time = duration;
+        app.reset_state();
+        // An inner algebraic loop is reported by validation before any run.
+        let order = app.evaluation_order().unwrap_or_default();
+        let unconnected_sinks = app.unconnected_sinks();
+        SubsystemRun {
+            app,
+            run: Run {
+                order,
+                step: 0,
+                steps: usize::MAX,
+                outputs: HashMap::new(),
+                unconnected_sinks,
+            },
+        }
+    }
+
+    fn evaluate(&mut self, inputs: Vec<f32>, outputs: usize) -> Vec<f32> {
+        self.app.inport_values = inputs;
+        self.app.outport_values = vec![0.0; outputs];
+        // Divergence inside shows up on the subsystem's own outputs.
+        let _ = self.app.advance(&mut self.run);
+        std::mem::take(&mut self.app.outport_values)
+    }
+}
+
+// SplitMix64, small enough to keep noise sources reproducible per seed
+// without pulling in an RNG crate.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in (0, 1].
+    fn next_unit(&mut self) -> f64 {
+        ((self.next_u64() >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    // Standard normal sample via Box-Muller.
+    fn next_gaussian(&mut self) -> f32 {
+        let (u1, u2) = (self.next_unit(), self.next_unit());
+        ((-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()) as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SolverMethod {
+    Euler,
+    Rk4,
+}
+
+impl ComponentState {
+    // `duration` is the length of the run the state is for.
+    fn new(component_type: &ComponentType, dt: f32, solver: SolverMethod, duration: f32) -> Self {
+        match component_type {
+            ComponentType::Step
+            | ComponentType::Scope
+            | ComponentType::XYScope
+            | ComponentType::NumericDisplay { .. }
+            | ComponentType::Terminator
+            | ComponentType::Gain { .. }
+            | ComponentType::Sum { .. }
+            | ComponentType::Constant { .. }
+            | ComponentType::Sine { .. }
+            | ComponentType::MultiSine { .. }
+            | ComponentType::Ramp { .. }
+            | ComponentType::Clock
+            | ComponentType::Pulse { .. }
+            | ComponentType::Saturation { .. }
+            | ComponentType::LookupTable { .. }
+            | ComponentType::Switch { .. }
+            | ComponentType::Abs
+            | ComponentType::Sign
+            | ComponentType::Quantizer { .. }
+            | ComponentType::MinMax { .. }
+            | ComponentType::Inport { .. }
+            | ComponentType::Outport { .. }
+            | ComponentType::Mux { .. }
+            | ComponentType::Demux { .. }
+            | ComponentType::Product => ComponentState::Stateless,
+            ComponentType::Subsystem { model } => {
+                ComponentState::Subsystem(Box::new(SubsystemRun::new(model, dt, solver, duration)))
+            }
+            ComponentType::TransferFunction | ComponentType::LowPass { .. } => {
+                ComponentState::Filter { output: 0.0 }
+            }
+            ComponentType::Delay { steps, .. } => ComponentState::Delay {
+                buffer: VecDeque::with_capacity(*steps + 1),
+            },
+            ComponentType::Difference => ComponentState::PreviousInput(None),
+            ComponentType::Memory { initial } => ComponentState::Hold { value: *initial },
+            ComponentType::DiscreteIntegrator | ComponentType::ErrorIntegral { .. } => {
+                ComponentState::Integrator {
+                    accumulator: 0.0,
+                    prev_input: None,
+                    prev_reset: None,
+                }
+            }
+            ComponentType::IntegratorIC { initial, .. } => ComponentState::Integrator {
+                accumulator: *initial,
+                prev_input: None,
+                prev_reset: None,
+            },
+            ComponentType::PIDController { .. } => ComponentState::Pid {
+                prev_error: 0.0,
+                integral: 0.0,
+            },
+            ComponentType::ZeroOrderHold { .. } => ComponentState::Hold { value: 0.0 },
+            ComponentType::RateLimiter { .. } => ComponentState::PreviousOutput(0.0),
+            ComponentType::Derivative { .. }
+            | ComponentType::DiscreteDerivative
+            | ComponentType::FilteredDerivative { .. } => ComponentState::Derivative {
+                prev_input: None,
+                output: 0.0,
+            },
+            ComponentType::Relay { .. } => ComponentState::Relay { on: false },
+            ComponentType::TransportDelay { seconds } => {
+                // Outputs only depend on past inputs, so the delay is at least one
+                // step. A delay past the end of the run outputs zero throughout,
+                // so the buffer never needs to be longer than the run.
+                let samples = (seconds / dt).min((duration / dt).ceil() + 1.0).max(1.0);
+                ComponentState::TransportDelay {
+                    buffer: VecDeque::from(vec![0.0; samples.floor() as usize + 2]),
+                    samples,
+                }
+            }
+            ComponentType::Noise { seed, .. } => ComponentState::Noise(Rng(*seed)),
+            ComponentType::StateSpace { a, b, c, d } => {
+                let order = a.len();
+                let resized = |v: &[f32]| {
+                    let mut v = v.to_vec();
+                    v.resize(order, 0.0);
+                    v
+                };
+                ComponentState::StateSpace {
+                    a: a.iter().map(|row| resized(row)).collect(),
+                    b: resized(b),
+                    c: resized(c),
+                    d: *d,
+                    x: vec![0.0; order],
+                    prev_input: None,
+                }
+            }
+            ComponentType::TransferFunctionCoeffs { num, den } => match solver {
+                SolverMethod::Rk4 => state_space_from_tf(num, den).unwrap_or_else(|| {
+                    let (b, a) = tustin(num, den, dt);
+                    ComponentState::linear_filter(b, a)
+                }),
+                SolverMethod::Euler => {
+                    let (b, a) = tustin(num, den, dt);
+                    ComponentState::linear_filter(b, a)
+                }
+            },
+            ComponentType::DiscreteTransferFunction { num, den } => {
+                let (b, a) = z_domain(num, den);
+                ComponentState::linear_filter(b, a)
+            }
+        }
+    }
+
+    fn linear_filter(b: Vec<f32>, a: Vec<f32>) -> Self {
+        let order = b.len().max(a.len()).saturating_sub(1);
+        ComponentState::LinearFilter {
+            b,
+            a,
+            inputs: VecDeque::from(vec![0.0; order]),
+            outputs: VecDeque::from(vec![0.0; order]),
+        }
+    }
+}
+
+// Controllable canonical form of a proper `num(s) / den(s)`.
+fn state_space_from_tf(num: &[f32], den: &[f32]) -> Option<ComponentState> {
+    let lead = *den.first()?;
+    if num.len() > den.len() || lead == 0.0 {
+        return None;
+    }
+    let order = den.len() - 1;
+    let a_coeffs: Vec<f32> = den.iter().map(|coeff| coeff / lead).collect();
+    let mut b_coeffs = vec![0.0; den.len() - num.len()];
+    b_coeffs.extend(num.iter().map(|coeff| coeff / lead));
+
+    let mut a = vec![vec![0.0; order]; order];
+    for (j, coeff) in a_coeffs.iter().skip(1).enumerate() {
+        a[0][j] = -coeff;
+    }
+    for (i, row) in a.iter_mut().enumerate().skip(1) {
+        row[i - 1] = 1.0;
+    }
+    let mut b = vec![0.0; order];
+    if let Some(first) = b.first_mut() {
+        *first = 1.0;
+    }
+    let d = b_coeffs[0];
+    let c = (1..=order).map(|i| b_coeffs[i] - a_coeffs[i] * d).collect();
+    Some(ComponentState::StateSpace {
+        a,
+        b,
+        c,
+        d,
+        x: vec![0.0; order],
+        prev_input: None,
+    })
+}
+
+// Classic fourth-order Runge-Kutta step of `x' = f(tau, x)` over `[0, dt]`.
+fn rk4_step(x: &mut [f32], dt: f32, f: impl Fn(f32, &[f32]) -> Vec<f32>) {
+    let offset = |x: &[f32], k: &[f32], scale: f32| -> Vec<f32> {
+        x.iter().zip(k).map(|(xi, ki)| xi + ki * scale).collect()
+    };
+    let k1 = f(0.0, x);
+    let k2 = f(dt / 2.0, &offset(x, &k1, dt / 2.0));
+    let k3 = f(dt / 2.0, &offset(x, &k2, dt / 2.0));
+    let k4 = f(dt, &offset(x, &k3, dt));
+    for (i, xi) in x.iter_mut().enumerate() {
+        *xi += dt / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]);
+    }
+}
+
+fn integrate(
+    accumulator: &mut f32,
+    prev_input: &mut Option<f32>,
+    input: f32,
+    dt: f32,
+    solver: SolverMethod,
+) {
+    match solver {
+        SolverMethod::Euler => *accumulator += input * dt,
+        SolverMethod::Rk4 => {
+            let u = extrapolated_input(input, *prev_input, dt);
+            let mut x = [*accumulator];
+            rk4_step(&mut x, dt, |tau, _| vec![u(tau)]);
+            *accumulator = x[0];
+        }
+    }
+    *prev_input = Some(input);
+}
+
+// Inputs are only known at step boundaries, so within a step the solver
+// extrapolates linearly from the previous sample to the current one.
+fn extrapolated_input(input: f32, prev_input: Option<f32>, dt: f32) -> impl Fn(f32) -> f32 {
+    let slope = prev_input.map_or(0.0, |prev| (input - prev) / dt);
+    move |tau| input + slope * tau
+}
+
+// Downhill simplex minimisation of `f` from `start`, stopping once the
+// simplex values agree or `max_evaluations` is reached.
+fn nelder_mead(
+    mut f: impl FnMut(&[f32]) -> f32,
+    start: &[f32],
+    max_evaluations: usize,
+) -> (Vec<f32>, f32) {
+    let n = start.len();
+    let evaluations = std::cell::Cell::new(0);
+    let mut eval = |x: &[f32]| {
+        evaluations.set(evaluations.get() + 1);
+        let value = f(x);
+        if value.is_nan() {
+            f32::INFINITY
+        } else {
+            value
+        }
+    };
+    let mut simplex: Vec<(Vec<f32>, f32)> = vec![(start.to_vec(), eval(start))];
+    for i in 0..n {
+        let mut x = start.to_vec();
+        x[i] += if x[i] == 0.0 { 0.5 } else { 0.5 * x[i] };
+        let value = eval(&x);
+        simplex.push((x, value));
+    }
+    let along = |from: &[f32], to: &[f32], t: f32| -> Vec<f32> {
+        from.iter().zip(to).map(|(a, b)| a + t * (b - a)).collect()
+    };
+
+    while evaluations.get() < max_evaluations {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if (simplex[n].1 - simplex[0].1).abs() <= 1e-6 * (1.0 + simplex[0].1.abs()) {
+            break;
+        }
+        let centroid: Vec<f32> = (0..n)
+            .map(|j| simplex[..n].iter().map(|(x, _)| x[j]).sum::<f32>() / n as f32)
+            .collect();
+        let worst = simplex[n].clone();
+        let reflected = along(&centroid, &worst.0, -1.0);
+        let reflected_value = eval(&reflected);
+        if reflected_value < simplex[0].1 {
+            let expanded = along(&centroid, &worst.0, -2.0);
+            let expanded_value = eval(&expanded);
+            simplex[n] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            let contracted = along(&centroid, &worst.0, 0.5);
+            let contracted_value = eval(&contracted);
+            if contracted_value < worst.1 {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                let best = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    vertex.0 = along(&best, &vertex.0, 0.5);
+                    vertex.1 = eval(&vertex.0);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    simplex.swap_remove(0)
+}
+
+// Roots of a polynomial in descending powers by Durand–Kerner iteration.
+// Leading zero coefficients are ignored.
+fn poly_roots(coeffs: &[f32]) -> Vec<Complex<f64>> {
+    let coeffs: Vec<f64> = coeffs
+        .iter()
+        .skip_while(|c| **c == 0.0)
+        .map(|c| *c as f64)
+        .collect();
+    if coeffs.len() < 2 {
+        return Vec::new();
+    }
+    let monic: Vec<f64> = coeffs.iter().map(|c| c / coeffs[0]).collect();
+    let eval = |z: Complex<f64>| {
+        monic
+            .iter()
+            .fold(Complex::new(0.0, 0.0), |acc, c| acc * z + *c)
+    };
+    let degree = monic.len() - 1;
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<Complex<f64>> = (0..degree).map(|k| seed.powu(k as u32)).collect();
+    for _ in 0..500 {
+        let mut largest_step: f64 = 0.0;
+        for i in 0..degree {
+            let denominator = (0..degree)
+                .filter(|j| *j != i)
+                .fold(Complex::new(1.0, 0.0), |acc, j| acc * (roots[i] - roots[j]));
+            let step = eval(roots[i]) / denominator;
+            if step.is_finite() {
+                roots[i] -= step;
+                largest_step = largest_step.max(step.norm());
+            }
+        }
+        if largest_step < 1e-12 {
+            break;
+        }
+    }
+    roots
+}
+
+fn poly_mul(p: &[f64], q: &[f64]) -> Vec<f64> {
+    let mut result = vec![0.0; p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            result[i + j] += a * b;
+        }
+    }
+    result
+}
+
+// Bilinear transform of `num(s) / den(s)` (coefficients in descending powers
+// of s) into z^-1 coefficients normalized so that `a[0] == 1`.
+fn tustin(num: &[f32], den: &[f32], dt: f32) -> (Vec<f32>, Vec<f32>) {
+    let order = num.len().max(den.len()).saturating_sub(1);
+    let c = 2.0 / dt as f64;
+    let discretize = |coeffs: &[f32]| {
+        let padding = order + 1 - coeffs.len();
+        let mut result = vec![0.0; order + 1];
+        for (i, coeff) in coeffs.iter().enumerate() {
+            let power = order - (padding + i);
+            let mut term = vec![*coeff as f64 * c.powi(power as i32)];
+            for _ in 0..power {
+                term = poly_mul(&term, &[1.0, -1.0]);
+            }
+            for _ in power..order {
+                term = poly_mul(&term, &[1.0, 1.0]);
+            }
+            for (r, t) in result.iter_mut().zip(term) {
+                *r += t;
+            }
+        }
+        result
+    };
+
+    let b = discretize(num);
+    let a = discretize(den);
+    if a[0].abs() < f64::EPSILON {
+        return (vec![0.0], vec![1.0]);
+    }
+    (
+        b.iter().map(|v| (v / a[0]) as f32).collect(),
+        a.iter().map(|v| (v / a[0]) as f32).collect(),
+    )
+}
+
+// Difference equation coefficients in powers of z^-1 for `num(z) / den(z)`
+// given in descending powers of z, normalised so that a[0] = 1. An improper
+// or empty denominator yields a block that outputs zero.
+fn z_domain(num: &[f32], den: &[f32]) -> (Vec<f32>, Vec<f32>) {
+    let len = num.len().max(den.len());
+    let padded = |coeffs: &[f32]| {
+        let mut result = vec![0.0; len - coeffs.len()];
+        result.extend_from_slice(coeffs);
+        result
+    };
+    let (b, a) = (padded(num), padded(den));
+    match a.first() {
+        Some(&a0) if a0 != 0.0 => (
+            b.iter().map(|v| v / a0).collect(),
+            a.iter().map(|v| v / a0).collect(),
+        ),
+        _ => (vec![0.0], vec![1.0]),
+    }
+}
+
+// Linear interpolation through ascending `breakpoints`, holding the end
+// values outside the table.
+fn lookup(breakpoints: &[f32], values: &[f32], x: f32) -> f32 {
+    let n = breakpoints.len().min(values.len());
+    if n == 0 {
+        return 0.0;
+    }
+    if x <= breakpoints[0] {
+        return values[0];
+    }
+    for i in 1..n {
+        if x <= breakpoints[i] {
+            let span = breakpoints[i] - breakpoints[i - 1];
+            if span <= 0.0 {
+                return values[i];
+            }
+            let fraction = (x - breakpoints[i - 1]) / span;
+            return values[i - 1] + fraction * (values[i] - values[i - 1]);
+        }
+    }
+    values[n - 1]
+}
+
+// Magnitude (dB) and unwrapped phase (deg) of `num(s) / den(s)` at each
+// angular frequency in `omegas`.
+fn frequency_response(num: &[f32], den: &[f32], omegas: &[f64]) -> Vec<(f64, f64)> {
+    // Horner evaluation of a polynomial in s = jw, returned as (re, im).
+    let eval = |coeffs: &[f32], omega: f64| {
+        coeffs.iter().fold((0.0, 0.0), |(re, im), &c| {
+            (-im * omega + c as f64, re * omega)
+        })
+    };
+    let mut response = Vec::with_capacity(omegas.len());
+    let mut prev_phase: Option<f64> = None;
+    for &omega in omegas {
+        let (n_re, n_im) = eval(num, omega);
+        let (d_re, d_im) = eval(den, omega);
+        let magnitude = n_re.hypot(n_im) / d_re.hypot(d_im);
+        let mut phase = (n_im.atan2(n_re) - d_im.atan2(d_re)).to_degrees();
+        if let Some(prev) = prev_phase {
+            phase -= ((phase - prev) / 360.0).round() * 360.0;
+        }
+        prev_phase = Some(phase);
+        response.push((20.0 * magnitude.log10(), phase));
+    }
+    response
+}
+
+// Single-sided amplitude spectrum of `data` sampled every `dt`, as
+// (frequency in Hz, amplitude) pairs. The samples are zero-padded to a power
+// of two, and amplitudes are scaled so a sinusoid reads as its amplitude.
+fn amplitude_spectrum(data: &[f32], dt: f32) -> Vec<[f64; 2]> {
+    if data.is_empty() || dt <= 0.0 {
+        return Vec::new();
+    }
+    let n = data.len().next_power_of_two();
+    let mut buffer: Vec<Complex<f64>> = data
+        .iter()
+        .map(|&x| Complex::new(x as f64, 0.0))
+        .chain(std::iter::repeat(Complex::new(0.0, 0.0)))
+        .take(n)
+        .collect();
+    FftPlanner::new().plan_fft_forward(n).process(&mut buffer);
+    let df = 1.0 / (n as f64 * dt as f64);
+    buffer[..=n / 2]
+        .iter()
+        .enumerate()
+        .map(|(k, bin)| {
+            let scale = if k == 0 || k == n / 2 { 1.0 } else { 2.0 };
+            [k as f64 * df, scale * bin.norm() / data.len() as f64]
+        })
+        .collect()
+}
+
+// One step of a direct form I difference equation, with `inputs` and
+// `outputs` holding the most recent samples first.
+fn filter_step(
+    b: &[f32],
+    a: &[f32],
+    inputs: &mut VecDeque<f32>,
+    outputs: &mut VecDeque<f32>,
+    input: f32,
+) -> f32 {
+    let mut output = b.first().copied().unwrap_or(0.0) * input;
+    for (coeff, past) in b.iter().skip(1).zip(inputs.iter()) {
+        output += coeff * past;
+    }
+    for (coeff, past) in a.iter().skip(1).zip(outputs.iter()) {
+        output -= coeff * past;
+    }
+    if !inputs.is_empty() {
+        inputs.pop_back();
+        inputs.push_front(input);
+        outputs.pop_back();
+        outputs.push_front(output);
+    }
+    output
+}
+
+struct StepMetrics {
+    rise_time: Option<f32>,
+    overshoot_percent: f32,
+    peak_time: f32,
+    settling_time: Option<f32>,
+}
+
+// Metrics of a step response sampled every `dt`. The final value is the mean
+// of the last fifth of the trace, and a response that leaves the ±2% band
+// within that fifth has not settled.
+fn step_metrics(data: &[f32], dt: f32) -> Option<StepMetrics> {
+    let initial = *data.first()?;
+    let tail_len = (data.len() / 5).max(1);
+    let tail = &data[data.len() - tail_len..];
+    let final_value = tail.iter().sum::<f32>() / tail.len() as f32;
+    let span = final_value - initial;
+    if span.abs() < f32::EPSILON {
+        return None;
+    }
+    // Normalized so the response always rises from 0 towards 1.
+    let normalized: Vec<f32> = data.iter().map(|v| (v - initial) / span).collect();
+    let time = |i: usize| i as f32 * dt;
+
+    let rise_start = normalized.iter().position(|&v| v >= 0.1);
+    let rise_end = normalized.iter().position(|&v| v >= 0.9);
+    let rise_time = match (rise_start, rise_end) {
+        (Some(start), Some(end)) => Some(time(end) - time(start)),
+        _ => None,
+    };
+
+    let (peak_index, peak) = normalized
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    let overshoot_percent = ((peak - 1.0) * 100.0).max(0.0);
+
+    let settling_time = match normalized.iter().rposition(|v| (v - 1.0).abs() > 0.02) {
+        None => Some(0.0),
+        Some(last) if last + tail_len < normalized.len() => Some(time(last + 1)),
+        Some(_) => None,
+    };
+
+    Some(StepMetrics {
+        rise_time,
+        overshoot_percent,
+        peak_time: time(peak_index),
+        settling_time,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Connection {
+    gain: f32,
+    #[serde(default)]
+    from_port: usize,
+    #[serde(default)]
+    to_port: usize,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            gain: 1.0,
+            from_port: 0,
+            to_port: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PortKind {
+    Input,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PortRef {
+    component: usize,
+    kind: PortKind,
+    index: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "RecordFields", into = "RecordFields")]
+struct ConnectionRecord {
+    from: usize,
+    to: usize,
+    connection: Connection,
+}
+
+// The on-disk layout of a ConnectionRecord, spelled out instead of using
+// `#[serde(flatten)]` so that non-self-describing formats like bincode work.
+#[derive(Serialize, Deserialize)]
+struct RecordFields {
+    from: usize,
+    to: usize,
+    gain: f32,
+    #[serde(default)]
+    from_port: usize,
+    #[serde(default)]
+    to_port: usize,
+}
+
+impl From<RecordFields> for ConnectionRecord {
+    fn from(fields: RecordFields) -> Self {
+        ConnectionRecord {
+            from: fields.from,
+            to: fields.to,
+            connection: Connection {
+                gain: fields.gain,
+                from_port: fields.from_port,
+                to_port: fields.to_port,
+            },
+        }
+    }
+}
+
+impl From<ConnectionRecord> for RecordFields {
+    fn from(record: ConnectionRecord) -> Self {
+        RecordFields {
+            from: record.from,
+            to: record.to,
+            gain: record.connection.gain,
+            from_port: record.connection.from_port,
+            to_port: record.connection.to_port,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum EditCommand {
+    AddComponent(Component),
+    RemoveComponent {
+        component: Component,
+        connections: Vec<ConnectionRecord>,
+    },
+    Connect(ConnectionRecord),
+    Disconnect(Vec<ConnectionRecord>),
+    // A wire's gain or input port changed in place.
+    EditConnection {
+        old: ConnectionRecord,
+        new: Connection,
+    },
+    // Several edits undone and redone together, in order.
+    Batch(Vec<EditCommand>),
+}
+
+#[derive(Debug, Clone)]
+struct Clipboard {
+    components: Vec<Component>,
+    connections: Vec<ConnectionRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ModelFile {
+    components: Vec<Component>,
+    connections: Vec<ConnectionRecord>,
+    #[serde(default)]
+    view: Option<ViewState>,
+    // Only the root model carries metadata; subsystems leave it empty.
+    #[serde(default)]
+    metadata: Option<ModelMetadata>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ModelMetadata {
+    title: String,
+    author: String,
+    notes: String,
+    // Seconds since the Unix epoch, or 0 when unknown.
+    created: u64,
+}
+
+impl ModelMetadata {
+    fn now() -> Self {
+        let created = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        ModelMetadata {
+            created,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ViewState {
+    camera: Camera,
+    selected: Vec<usize>,
+    #[serde(default)]
+    plot_y: PlotYRange,
+}
+
+// Vertical range of the scope plot: fitted to the traces, or held fixed with
+// samples outside it drawn clipped to the nearest edge.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+enum PlotYRange {
+    #[default]
+    Auto,
+    Fixed {
+        min: f32,
+        max: f32,
+    },
+}
+
+impl ModelFile {
+    // Input and output port counts when used as a subsystem: one port per
+    // index up to the highest Inport and Outport inside.
+    fn boundary_ports(&self) -> (usize, usize) {
+        let mut ports = (0, 0);
+        for component in &self.components {
+            match component.component_type {
+                ComponentType::Inport { index } => ports.0 = ports.0.max(index + 1),
+                ComponentType::Outport { index } => ports.1 = ports.1.max(index + 1),
+                _ => {}
+            }
+        }
+        (
+            ports.0.min(SUBSYSTEM_INPUTS.len()),
+            ports.1.min(SUBSYSTEM_OUTPUTS.len()),
+        )
+    }
+}
+
+impl Component {
+    fn label(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| self.component_type.label())
+    }
+
+    fn rect(&self) -> egui::Rect {
+        let size = if self.injection_point {
+            egui::vec2(20.0, 20.0)
+        } else {
+            egui::vec2(80.0, 40.0)
+        };
+        egui::Rect::from_center_size(self.position.clone().into(), size)
+    }
+
+    fn port_count(&self, kind: PortKind) -> usize {
+        match kind {
+            PortKind::Input => self.component_type.input_ports().len(),
+            PortKind::Output => self.component_type.output_ports().len(),
+        }
+    }
+
+    // Inputs sit on the left edge and outputs on the right, evenly spaced.
+    fn port_pos(&self, kind: PortKind, index: usize) -> Pos2 {
+        let rect = self.rect();
+        let spacing = rect.height() / (self.port_count(kind) as f32 + 1.0);
+        let x = match kind {
+            PortKind::Input => rect.left(),
+            PortKind::Output => rect.right(),
+        };
+        egui::pos2(x, rect.top() + spacing * (index as f32 + 1.0))
+    }
+
+    fn ports(&self) -> impl Iterator<Item = PortRef> + '_ {
+        [PortKind::Input, PortKind::Output]
+            .into_iter()
+            .flat_map(move |kind| {
+                (0..self.port_count(kind)).map(move |index| PortRef {
+                    component: self.id,
+                    kind,
+                    index,
+                })
+            })
+    }
+}
+
+fn distance_to_segment(point: Pos2, a: Pos2, b: Pos2) -> f32 {
+    let ab = b - a;
+    let length_sq = ab.length_sq();
+    if length_sq == 0.0 {
+        return point.distance(a);
+    }
+    let t = ((point - a).dot(ab) / length_sq).clamp(0.0, 1.0);
+    point.distance(a + ab * t)
+}
+
+const PORT_RADIUS: f32 = 4.0;
+
+// Length of the horizontal run a wire keeps next to a port before turning.
+const WIRE_STUB: f32 = 15.0;
+
+// Orthogonal path in world space from an output port to an input port. A
+// forward wire turns once halfway across; one that runs backwards leaves
+// and enters horizontally and loops underneath both blocks.
+fn wire_route(from: Pos2, to: Pos2, from_rect: egui::Rect, to_rect: egui::Rect) -> Vec<Pos2> {
+    if to.x - from.x >= 2.0 * WIRE_STUB {
+        let mid_x = (from.x + to.x) / 2.0;
+        return vec![from, egui::pos2(mid_x, from.y), egui::pos2(mid_x, to.y), to];
+    }
+    let out_x = from.x + WIRE_STUB;
+    let in_x = to.x - WIRE_STUB;
+    let below = from_rect.bottom().max(to_rect.bottom()) + WIRE_STUB;
+    vec![
+        from,
+        egui::pos2(out_x, from.y),
+        egui::pos2(out_x, below),
+        egui::pos2(in_x, below),
+        egui::pos2(in_x, to.y),
+        to,
+    ]
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Camera {
+    pan: egui::Vec2,
+    zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            pan: egui::Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    fn to_screen(self, pos: Pos2) -> Pos2 {
+        (pos.to_vec2() * self.zoom + self.pan).to_pos2()
+    }
+
+    fn to_world(self, pos: Pos2) -> Pos2 {
+        ((pos.to_vec2() - self.pan) / self.zoom).to_pos2()
+    }
+
+    fn rect_to_screen(self, rect: egui::Rect) -> egui::Rect {
+        egui::Rect::from_min_max(self.to_screen(rect.min), self.to_screen(rect.max))
+    }
+
+    // Frames all of `content` (world space) inside `viewport` with a margin,
+    // without zooming in past 1:1.
+    fn fit(content: egui::Rect, viewport: egui::Rect) -> Self {
+        let content = content.expand(40.0);
+        let zoom = (viewport.width() / content.width())
+            .min(viewport.height() / content.height())
+            .clamp(0.1, 1.0);
+        Camera {
+            pan: viewport.center().to_vec2() - content.center().to_vec2() * zoom,
+            zoom,
+        }
+    }
+
+    // Puts the world point `target` at the centre of `viewport`, zooming in
+    // to at least 1:1.
+    fn center_on(&mut self, target: Pos2, viewport: egui::Rect) {
+        self.zoom = self.zoom.max(1.0);
+        self.pan = viewport.center().to_vec2() - target.to_vec2() * self.zoom;
+    }
+
+    // Keeps the world point under `anchor` fixed on screen.
+    fn zoom_around(&mut self, anchor: Pos2, factor: f32) {
+        let world = self.to_world(anchor);
+        self.zoom = (self.zoom * factor).clamp(0.1, 10.0);
+        self.pan = anchor.to_vec2() - world.to_vec2() * self.zoom;
+    }
+}
+
+// A subsystem that starts out as a pass-through from one Inport to one Outport.
+fn pass_through_subsystem() -> ComponentType {
+    let mut inner = SimulatorApp::new();
+    inner.add_component(ComponentType::Inport { index: 0 }, Pos2::new(50.0, 100.0));
+    inner.add_component(ComponentType::Outport { index: 0 }, Pos2::new(250.0, 100.0));
+    let _ = inner.connect_components(0, 0, 1, 0);
+    ComponentType::Subsystem {
+        model: inner.to_model(),
+    }
+}
+
+// A noisy sine differentiated both raw and filtered, to show how a raw
+// derivative amplifies high-frequency noise.
+fn derivative_demo() -> ModelFile {
+    let mut demo = SimulatorApp::new();
+    let sine = ComponentType::Sine {
+        amplitude: 1.0,
+        freq_hz: 0.5,
+        phase: 0.0,
+    };
+    let noise = ComponentType::Noise {
+        std_dev: 0.01,
+        seed: 1,
+    };
+    let sum = ComponentType::Sum {
+        signs: vec![1.0, 1.0],
+    };
+    let raw = ComponentType::Derivative {
+        filtered: false,
+        n: 20.0,
+    };
+    let filtered = ComponentType::Derivative {
+        filtered: true,
+        n: 20.0,
+    };
+    demo.add_component(sine, Pos2::new(50.0, 80.0));
+    demo.add_component(noise, Pos2::new(50.0, 180.0));
+    demo.add_component(sum, Pos2::new(200.0, 130.0));
+    demo.add_component(raw, Pos2::new(350.0, 80.0));
+    demo.add_component(filtered, Pos2::new(350.0, 180.0));
+    demo.add_component(ComponentType::Scope, Pos2::new(500.0, 80.0));
+    demo.add_component(ComponentType::Scope, Pos2::new(500.0, 180.0));
+    let _ = demo.connect_components(0, 0, 2, 0);
+    let _ = demo.connect_components(1, 0, 2, 1);
+    for (from, to) in [(2, 3), (2, 4), (3, 5), (4, 6)] {
+        let _ = demo.connect_components(from, 0, to, 0);
+    }
+    let mut model = demo.to_model();
+    model.metadata = Some(ModelMetadata {
+        title: "Raw vs filtered derivative".to_string(),
+        notes: "Run it and compare the two scopes: the raw derivative turns the \
+                small noise into large spikes, the filtered one rolls it off above N."
+            .to_string(),
+        ..ModelMetadata::now()
+    });
+    model
+}
+
+// Every block type with its default settings, grouped for the palette.
+fn block_palette() -> Vec<(&'static str, Vec<(&'static str, ComponentType)>)> {
+    vec![
+        (
+            "Sources",
+            vec![
+                ("Step", ComponentType::Step),
+                ("Constant", ComponentType::Constant { value: 1.0 }),
+                (
+                    "Sine",
+                    ComponentType::Sine {
+                        amplitude: 1.0,
+                        freq_hz: 1.0,
+                        phase: 0.0,
+                    },
+                ),
+                (
+                    "Multi-Sine",
+                    ComponentType::MultiSine {
+                        components: vec![(1.0, 0.1, 0.0), (1.0, 1.0, 0.0), (1.0, 10.0, 0.0)],
+                    },
+                ),
+                (
+                    "Ramp",
+                    ComponentType::Ramp {
+                        slope: 1.0,
+                        start_time: 0.0,
+                    },
+                ),
+                ("Clock", ComponentType::Clock),
+                (
+                    "Pulse",
+                    ComponentType::Pulse {
+                        amplitude: 1.0,
+                        period: 2.0,
+                        duty: 0.5,
+                    },
+                ),
+                (
+                    "Noise",
+                    ComponentType::Noise {
+                        std_dev: 0.1,
+                        seed: 0,
+                    },
+                ),
+                ("Inport", ComponentType::Inport { index: 0 }),
+            ],
+        ),
+        (
+            "Linear",
+            vec![
+                ("Gain", ComponentType::Gain { k: 2.0 }),
+                (
+                    "Sum",
+                    ComponentType::Sum {
+                        signs: vec![1.0, -1.0],
+                    },
+                ),
+                ("Transfer Function", ComponentType::TransferFunction),
+                ("Low-Pass", ComponentType::LowPass { tau: 1.0 }),
+                (
+                    "TF(s)",
+                    ComponentType::TransferFunctionCoeffs {
+                        num: vec![1.0],
+                        den: vec![1.0, 1.0],
+                    },
+                ),
+                (
+                    "State Space",
+                    ComponentType::StateSpace {
+                        a: vec![vec![-1.0]],
+                        b: vec![1.0],
+                        c: vec![1.0],
+                        d: 0.0,
+                    },
+                ),
+                (
+                    "Derivative",
+                    ComponentType::Derivative {
+                        filtered: true,
+                        n: 10.0,
+                    },
+                ),
+                (
+                    "Transport Delay",
+                    ComponentType::TransportDelay { seconds: 0.5 },
+                ),
+            ],
+        ),
+        (
+            "Nonlinear",
+            vec![
+                ("Product", ComponentType::Product),
+                (
+                    "Saturation",
+                    ComponentType::Saturation {
+                        min: -1.0,
+                        max: 1.0,
+                    },
+                ),
+                (
+                    "Lookup Table",
+                    ComponentType::LookupTable {
+                        breakpoints: vec![-1.0, 0.0, 1.0],
+                        values: vec![-1.0, 0.0, 1.0],
+                    },
+                ),
+                (
+                    "Rate Limiter",
+                    ComponentType::RateLimiter {
+                        rising: 1.0,
+                        falling: 1.0,
+                    },
+                ),
+                ("Abs", ComponentType::Abs),
+                ("Sign", ComponentType::Sign),
+                (
+                    "Relay",
+                    ComponentType::Relay {
+                        on_threshold: 0.5,
+                        off_threshold: -0.5,
+                        on_value: 1.0,
+                        off_value: 0.0,
+                    },
+                ),
+                (
+                    "MinMax",
+                    ComponentType::MinMax {
+                        mode: MinOrMax::Min,
+                    },
+                ),
+                ("Quantizer", ComponentType::Quantizer { step: 0.1 }),
+                ("Switch", ComponentType::Switch { threshold: 0.5 }),
+            ],
+        ),
+        (
+            "Discrete",
+            vec![
+                (
+                    "Delay",
+                    ComponentType::Delay {
+                        steps: 5,
+                        initial: 0.0,
+                    },
+                ),
+                ("Difference", ComponentType::Difference),
+                ("Integrator", ComponentType::DiscreteIntegrator),
+                (
+                    "Integrator IC",
+                    ComponentType::IntegratorIC {
+                        initial: 1.0,
+                        reset: None,
+                    },
+                ),
+                (
+                    "PID",
+                    ComponentType::PIDController {
+                        kp: 1.0,
+                        ki: 0.0,
+                        kd: 0.0,
+                        output_limits: None,
+                    },
+                ),
+                ("Memory", ComponentType::Memory { initial: 0.0 }),
+                ("ZOH", ComponentType::ZeroOrderHold { sample_every: 10 }),
+                (
+                    "TF(z)",
+                    ComponentType::DiscreteTransferFunction {
+                        num: vec![0.5],
+                        den: vec![1.0, -0.5],
+                    },
+                ),
+            ],
+        ),
+        (
+            "Sinks",
+            vec![
+                ("Scope", ComponentType::Scope),
+                ("XY Scope", ComponentType::XYScope),
+                ("Display", ComponentType::NumericDisplay { decimals: 3 }),
+                ("Terminator", ComponentType::Terminator),
+                (
+                    "Error Integral",
+                    ComponentType::ErrorIntegral {
+                        mode: ErrorCost::Iae,
+                    },
+                ),
+                ("Outport", ComponentType::Outport { index: 0 }),
+            ],
+        ),
+        (
+            "Structure",
+            vec![
+                ("Subsystem", pass_through_subsystem()),
+                ("Mux", ComponentType::Mux { width: 2 }),
+                ("Demux", ComponentType::Demux { width: 2 }),
+            ],
+        ),
+    ]
+}
+
+// Single-key shortcuts that add a block under the cursor.
+fn block_shortcuts() -> Vec<(egui::Key, &'static str, ComponentType)> {
+    vec![
+        (egui::Key::S, "Step", ComponentType::Step),
+        (
+            egui::Key::C,
+            "Constant",
+            ComponentType::Constant { value: 1.0 },
+        ),
+        (
+            egui::Key::N,
+            "Sine",
+            ComponentType::Sine {
+                amplitude: 1.0,
+                freq_hz: 1.0,
+                phase: 0.0,
+            },
+        ),
+        (
+            egui::Key::T,
+            "Transfer function",
+            ComponentType::TransferFunction,
+        ),
+        (
+            egui::Key::H,
+            "TF(s)",
+            ComponentType::TransferFunctionCoeffs {
+                num: vec![1.0],
+                den: vec![1.0, 1.0],
+            },
+        ),
+        (egui::Key::O, "Scope", ComponentType::Scope),
+        (
+            egui::Key::I,
+            "Integrator",
+            ComponentType::DiscreteIntegrator,
+        ),
+        (
+            egui::Key::P,
+            "PID",
+            ComponentType::PIDController {
+                kp: 1.0,
+                ki: 0.0,
+                kd: 0.0,
+                output_limits: None,
+            },
+        ),
+        (egui::Key::G, "Gain", ComponentType::Gain { k: 2.0 }),
+        (
+            egui::Key::A,
+            "Sum",
+            ComponentType::Sum {
+                signs: vec![1.0, -1.0],
+            },
+        ),
+        (
+            egui::Key::D,
+            "Delay",
+            ComponentType::Delay {
+                steps: 5,
+                initial: 0.0,
+            },
+        ),
+        (
+            egui::Key::L,
+            "Saturation",
+            ComponentType::Saturation {
+                min: -1.0,
+                max: 1.0,
+            },
+        ),
+    ]
+}
+
+// Marker on wires that carry a probe.
+const PROBE_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 140, 0);
+
+// Time spent advancing a full run each frame, so the UI stays responsive.
+const RUN_FRAME_BUDGET: Duration = Duration::from_millis(15);
+
+const TRACE_COLORS: [egui::Color32; 6] = [
+    egui::Color32::from_rgb(80, 160, 255),
+    egui::Color32::from_rgb(255, 120, 80),
+    egui::Color32::from_rgb(100, 200, 100),
+    egui::Color32::from_rgb(220, 100, 220),
+    egui::Color32::from_rgb(240, 200, 60),
+    egui::Color32::from_rgb(80, 210, 210),
+];
+
+struct SimulatorApp {
+    components: HashMap<usize, Component>,
+    connections: DiGraph<usize, Connection>,
+    next_id: usize,
+    selected_components: HashSet<usize>,
+    selected_connection: Option<EdgeIndex>,
+    pending_port: Option<PortRef>,
+    // World-space pointer position where the current drag began, with the
+    // positions of the dragged components at that moment.
+    drag_origin: Option<(Pos2, HashMap<usize, Pos2>)>,
+    rubber_band: Option<Pos2>,
+    // Block being dragged out of the palette, added where it is dropped.
+    palette_drag: Option<ComponentType>,
+    clipboard: Option<Clipboard>,
+    camera: Camera,
+    fit_pending: bool,
+    search: String,
+    // A block found by search, centred on the canvas next frame.
+    focus_pending: Option<usize>,
+    undo_stack: Vec<EditCommand>,
+    redo_stack: Vec<EditCommand>,
+    snap_to_grid: bool,
+    grid_size: f32,
+    simulation_data: HashMap<usize, Vec<f32>>,
+    // Scope traces snapshotted with "Pin", drawn dashed behind the live run.
+    pinned: Option<Vec<(String, Vec<[f64; 2]>)>>,
+    sweep_settings: SweepSettings,
+    // Scope traces of the last sweep, one per scope and value.
+    sweep: Vec<(String, Vec<[f64; 2]>)>,
+    sweep_run: Option<SweepRun>,
+    plot_y: PlotYRange,
+    // Value on each wire at the last simulated step, for colouring wires.
+    edge_values: HashMap<EdgeIndex, f32>,
+    // Wires being recorded without a Scope, named by the ports they join so
+    // they survive edits that renumber edges, with their (time, value) samples.
+    probes: Vec<(PortRef, PortRef)>,
+    probe_data: HashMap<(PortRef, PortRef), Vec<[f32; 2]>>,
+    // Clicking a wire toggles a probe on it instead of selecting it.
+    probe_mode: bool,
+    // Time of each recorded sample, shared by all scopes.
+    simulation_time: Vec<f32>,
+    xy_data: HashMap<usize, Vec<[f32; 2]>>,
+    // Latest input of each numeric display.
+    display_values: HashMap<usize, f32>,
+    component_states: HashMap<usize, ComponentState>,
+    last_error: Option<String>,
+    validation: Vec<ValidationError>,
+    run: Option<Run>,
+    playing: bool,
+    steps_per_frame: usize,
+    // Set by "Run Simulation": the live run advances to the end as fast as
+    // the frame budget allows, and can be cancelled part way.
+    running_to_end: bool,
+    analysis_scope: Option<usize>,
+    bode_decades: (f32, f32),
+    show_shortcuts: bool,
+    metadata: ModelMetadata,
+    show_metadata: bool,
+    // Last title handed to the window, so it is only set when it changes.
+    window_title: String,
+    context_menu: Option<ContextMenu>,
+    raised: Vec<usize>,
+    autotune: Option<Autotune>,
+    autotune_cost: Option<usize>,
+    dt: f32,
+    total_time: f32,
+    // Runs always stop on a non-finite output; with a bound set they also
+    // stop once any output grows past it.
+    divergence_bound: Option<f32>,
+    solver: SolverMethod,
+    // Prints every step and block value to stdout.
+    logging: bool,
+    // Boundary values while this app runs as the inside of a subsystem.
+    inport_values: Vec<f32>,
+    outport_values: Vec<f32>,
+    // The enclosing models while a subsystem is open for editing, outermost first.
+    edit_stack: Vec<ParentLevel>,
+}
+
+// Evenly spaced values tried for one parameter of the selected block.
+#[derive(Debug, Clone, Copy)]
+struct SweepSettings {
+    parameter: usize,
+    from: f32,
+    to: f32,
+    step: f32,
+}
+
+const MAX_SWEEP_RUNS: usize = 50;
+
+impl SweepSettings {
+    fn values(&self) -> Vec<f32> {
+        if self.step <= 0.0 || self.to < self.from {
+            return Vec::new();
+        }
+        // A little slack so rounding does not drop the end of the range.
+        let runs = ((self.to - self.from) / self.step + 1e-4).floor() as usize + 1;
+        (0..runs.min(MAX_SWEEP_RUNS))
+            .map(|i| self.from + i as f32 * self.step)
+            .collect()
+    }
+}
+
+// A named scope trace as (time, value) points.
+type Trace = (String, Vec<[f64; 2]>);
+
+enum SweepMessage {
+    Progress { runs: usize },
+    Done(Result<Vec<Trace>, String>),
+}
+
+// A parameter sweep running on a worker thread against a copy of the model.
+struct SweepRun {
+    receiver: mpsc::Receiver<SweepMessage>,
+    runs: usize,
+    total: usize,
+}
+
+enum AutotuneMessage {
+    Progress { evaluations: usize, best_cost: f32 },
+    Done(Result<([f32; 3], f32), String>),
+}
+
+// A PID search running on a worker thread against a copy of the model.
+struct Autotune {
+    pid: usize,
+    receiver: mpsc::Receiver<AutotuneMessage>,
+    evaluations: usize,
+    best_cost: f32,
+    finished: bool,
+}
+
+// Right-click menu for one component, anchored where it was opened.
+struct ContextMenu {
+    component: usize,
+    pos: Pos2,
+    rect: egui::Rect,
+}
+
+struct ParentLevel {
+    model: ModelFile,
+    subsystem: usize,
+    undo_stack: Vec<EditCommand>,
+    redo_stack: Vec<EditCommand>,
+}
+
+impl SimulatorApp {
+    fn new() -> Self {
+        SimulatorApp {
+            components: HashMap::new(),
+            connections: DiGraph::new(),
+            next_id: 0,
+            selected_components: HashSet::new(),
+            selected_connection: None,
+            pending_port: None,
+            drag_origin: None,
+            rubber_band: None,
+            palette_drag: None,
+            clipboard: None,
+            camera: Camera::default(),
+            fit_pending: false,
+            search: String::new(),
+            focus_pending: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            snap_to_grid: false,
+            grid_size: 20.0,
+            simulation_data: HashMap::new(),
+            pinned: None,
+            sweep_settings: SweepSettings {
+                parameter: 0,
+                from: 0.5,
+                to: 2.0,
+                step: 0.5,
+            },
+            sweep: Vec::new(),
+            sweep_run: None,
+            plot_y: PlotYRange::Auto,
+            edge_values: HashMap::new(),
+            probes: Vec::new(),
+            probe_data: HashMap::new(),
+            probe_mode: false,
+            simulation_time: Vec::new(),
+            xy_data: HashMap::new(),
+            display_values: HashMap::new(),
+            component_states: HashMap::new(),
+            last_error: None,
+            validation: Vec::new(),
+            run: None,
+            playing: false,
+            steps_per_frame: 1,
+            running_to_end: false,
+            analysis_scope: None,
+            bode_decades: (-2.0, 2.0),
+            show_shortcuts: false,
+            metadata: ModelMetadata::now(),
+            show_metadata: false,
+            window_title: String::new(),
+            context_menu: None,
+            raised: Vec::new(),
+            autotune: None,
+            autotune_cost: None,
+            dt: 0.1,
+            total_time: 10.0,
+            divergence_bound: None,
+            solver: SolverMethod::Euler,
+            logging: false,
+            inport_values: Vec::new(),
+            outport_values: Vec::new(),
+            edit_stack: Vec::new(),
+        }
+    }
+
+    fn add_component(&mut self, component_type: ComponentType, position: egui::Pos2) -> NodeIndex {
+        let id = self.next_id;
+        self.next_id += 1;
+        let component = Component {
+            id,
+            component_type,
+            position: position.into(),
+            is_dragging: false,
+            name: None,
+            injection_point: false,
+            enabled: true,
+        };
+        self.record(EditCommand::AddComponent(component.clone()));
+        self.insert_component(component)
+    }
+
+    // Splits the wire at `edge` with a hidden Sum and feeds a new Constant
+    // into it, so the disturbance is just more graph for `simulate()`.
+    fn inject_disturbance(&mut self, edge: EdgeIndex) {
+        let record = match self.connection_record(edge) {
+            Some(record) => record,
+            None => return,
+        };
+        let start =
+            self.components[&record.from].port_pos(PortKind::Output, record.connection.from_port);
+        let end = self.components[&record.to].port_pos(PortKind::Input, record.connection.to_port);
+        let middle = start + (end - start) / 2.0;
+        self.remove_connection(&record);
+        let mut commands = vec![EditCommand::Disconnect(vec![record.clone()])];
+
+        let junction = self.next_id;
+        let source = junction + 1;
+        self.next_id += 2;
+        let block = |id, component_type, position: egui::Pos2, injection_point| Component {
+            id,
+            component_type,
+            position: position.into(),
+            is_dragging: false,
+            name: None,
+            injection_point,
+            enabled: true,
+        };
+        let sum = ComponentType::Sum {
+            signs: vec![1.0, 1.0],
+        };
+        let constant = ComponentType::Constant { value: 0.1 };
+        for component in [
+            block(junction, sum, middle, true),
+            block(source, constant, middle - egui::vec2(0.0, 70.0), false),
+        ] {
+            commands.push(EditCommand::AddComponent(component.clone()));
+            self.insert_component(component);
+        }
+
+        let wires = [
+            ConnectionRecord {
+                from: record.from,
+                to: junction,
+                connection: Connection {
+                    to_port: 0,
+                    ..record.connection
+                },
+            },
+            ConnectionRecord {
+                from: junction,
+                to: record.to,
+                connection: Connection {
+                    to_port: record.connection.to_port,
+                    ..Connection::default()
+                },
+            },
+            ConnectionRecord {
+                from: source,
+                to: junction,
+                connection: Connection {
+                    to_port: 1,
+                    ..Connection::default()
+                },
+            },
+        ];
+        for wire in wires {
+            self.add_connection(&wire);
+            commands.push(EditCommand::Connect(wire));
+        }
+        self.record(EditCommand::Batch(commands));
+        self.selected_components = HashSet::from([source]);
+    }
+
+    // Adds a block from the palette, numbering boundary ports in the order
+    // they are added.
+    fn add_palette_block(&mut self, mut component_type: ComponentType, position: egui::Pos2) {
+        let (inputs, outputs) = self.to_model().boundary_ports();
+        match &mut component_type {
+            ComponentType::Inport { index } => *index = inputs,
+            ComponentType::Outport { index } => *index = outputs,
+            _ => {}
+        }
+        self.add_component(component_type, position);
+    }
+
+    // Steps `position` diagonally until no block sits on it, so blocks
+    // added at the same spot cascade instead of hiding each other.
+    fn cascade_position(&self, mut position: egui::Pos2) -> egui::Pos2 {
+        let step = egui::vec2(20.0, 20.0);
+        while self.components.values().any(|component| {
+            Pos2::from(component.position.clone()).distance(position) < step.x / 2.0
+        }) {
+            position += step;
+        }
+        position
+    }
+
+    fn insert_component(&mut self, mut component: Component) -> NodeIndex {
+        component.is_dragging = false;
+        let id = component.id;
+        self.components.insert(id, component);
+        self.connections.add_node(id)
+    }
+
+    fn take_component(&mut self, id: usize) -> Option<(Component, Vec<ConnectionRecord>)> {
+        let mut connections = Vec::new();
+        // `remove_node` moves the last node into the freed slot, so any stored
+        // indices are stale afterwards; components are always looked up by id.
+        if let Some(node_idx) = self.node_index(id) {
+            let mut edges: Vec<EdgeIndex> = self
+                .connections
+                .edges_directed(node_idx, petgraph::Incoming)
+                .chain(
+                    self.connections
+                        .edges_directed(node_idx, petgraph::Outgoing),
+                )
+                .map(|edge| edge.id())
+                .collect();
+            edges.sort();
+            edges.dedup();
+            connections = edges
+                .into_iter()
+                .filter_map(|edge| self.connection_record(edge))
+                .collect();
+            self.connections.remove_node(node_idx);
+            self.selected_connection = None;
+        }
+        self.component_states.remove(&id);
+        self.selected_components.remove(&id);
+        self.components
+            .remove(&id)
+            .map(|component| (component, connections))
+    }
+
+    fn selected_component(&self) -> Option<usize> {
+        match self.selected_components.len() {
+            1 => self.selected_components.iter().next().copied(),
+            _ => None,
+        }
+    }
+
+    // Copies and pastes the selection without touching the clipboard.
+    fn duplicate_selected(&mut self) {
+        let clipboard = self.clipboard.take();
+        self.copy_selected();
+        self.paste();
+        self.clipboard = clipboard;
+    }
+
+    fn nudge_selected(&mut self, offset: egui::Vec2) {
+        for id in &self.selected_components {
+            if let Some(component) = self.components.get_mut(id) {
+                let moved = egui::Pos2::from(component.position.clone()) + offset;
+                component.position = moved.into();
+            }
+        }
+    }
+
+    // Ids from back to front: blocks brought to front are drawn last, in the
+    // order they were raised.
+    fn draw_order(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self
+            .components
+            .keys()
+            .copied()
+            .filter(|id| !self.raised.contains(id))
+            .collect();
+        ids.sort();
+        ids.extend(
+            self.raised
+                .iter()
+                .filter(|id| self.components.contains_key(id)),
+        );
+        ids
+    }
+
+    fn bring_to_front(&mut self, id: usize) {
+        self.raised.retain(|raised| *raised != id);
+        self.raised.push(id);
+    }
+
+    // Copies the selected components along with the wires running between them.
+    fn copy_selected(&mut self) {
+        if self.selected_components.is_empty() {
+            return;
+        }
+        let mut components: Vec<Component> = self
+            .selected_components
+            .iter()
+            .filter_map(|id| self.components.get(id).cloned())
+            .collect();
+        components.sort_by_key(|component| component.id);
+        let connections = self
+            .connections
+            .edge_indices()
+            .filter_map(|edge| self.connection_record(edge))
+            .filter(|record| {
+                self.selected_components.contains(&record.from)
+                    && self.selected_components.contains(&record.to)
+            })
+            .collect();
+        self.clipboard = Some(Clipboard {
+            components,
+            connections,
+        });
+    }
+
+    fn paste(&mut self) {
+        let mut clipboard = match self.clipboard.take() {
+            Some(clipboard) => clipboard,
+            None => return,
+        };
+        self.selected_components.clear();
+        self.selected_connection = None;
+        let mut ids = HashMap::new();
+        for component in &mut clipboard.components {
+            // Repeated pastes cascade instead of stacking on the same spot.
+            let position = Pos2::from(component.position.clone()) + egui::vec2(20.0, 20.0);
+            component.position = position.into();
+            let node = self.add_component(component.component_type.clone(), position);
+            let id = self.connections[node];
+            ids.insert(component.id, id);
+            self.selected_components.insert(id);
+        }
+        for record in &clipboard.connections {
+            let record = ConnectionRecord {
+                from: ids[&record.from],
+                to: ids[&record.to],
+                connection: record.connection,
+            };
+            self.add_connection(&record);
+            self.record(EditCommand::Connect(record));
+        }
+        self.clipboard = Some(clipboard);
+    }
+
+    fn connection_record(&self, edge: EdgeIndex) -> Option<ConnectionRecord> {
+        let (from, to) = self.connections.edge_endpoints(edge)?;
+        Some(ConnectionRecord {
+            from: self.connections[from],
+            to: self.connections[to],
+            connection: self.connections[edge],
+        })
+    }
+
+    fn wire_ports(&self, edge: EdgeIndex) -> Option<(PortRef, PortRef)> {
+        let record = self.connection_record(edge)?;
+        Some((
+            PortRef {
+                component: record.from,
+                kind: PortKind::Output,
+                index: record.connection.from_port,
+            },
+            PortRef {
+                component: record.to,
+                kind: PortKind::Input,
+                index: record.connection.to_port,
+            },
+        ))
+    }
+
+    fn wire_edge(&self, wire: (PortRef, PortRef)) -> Option<EdgeIndex> {
+        self.connections
+            .edge_indices()
+            .find(|edge| self.wire_ports(*edge) == Some(wire))
+    }
+
+    fn toggle_probe(&mut self, edge: EdgeIndex) {
+        if let Some(wire) = self.wire_ports(edge) {
+            if let Some(i) = self.probes.iter().position(|probe| *probe == wire) {
+                self.probes.remove(i);
+                self.probe_data.remove(&wire);
+            } else {
+                self.probes.push(wire);
+            }
+        }
+    }
+
+    fn add_connection(&mut self, record: &ConnectionRecord) {
+        if let (Some(from_idx), Some(to_idx)) =
+            (self.node_index(record.from), self.node_index(record.to))
+        {
+            self.connections
+                .add_edge(from_idx, to_idx, record.connection);
+        }
+    }
+
+    // The wire joining the same ports as `connection`, whatever its gain.
+    fn find_connection(
+        &self,
+        from: usize,
+        to: usize,
+        connection: &Connection,
+    ) -> Option<EdgeIndex> {
+        let (from_idx, to_idx) = (self.node_index(from)?, self.node_index(to)?);
+        self.connections
+            .edges_connecting(from_idx, to_idx)
+            .find(|edge| {
+                edge.weight().from_port == connection.from_port
+                    && edge.weight().to_port == connection.to_port
+            })
+            .map(|edge| edge.id())
+    }
+
+    fn remove_connection(&mut self, record: &ConnectionRecord) {
+        if let Some(edge) = self.find_connection(record.from, record.to, &record.connection) {
+            self.connections.remove_edge(edge);
+            self.selected_connection = None;
+        }
+    }
+
+    // Changes the wire at `edge` in place. Consecutive gain changes to one
+    // wire, as from dragging the gain, undo together.
+    fn update_connection(&mut self, edge: EdgeIndex, connection: Connection) {
+        let old = match self.connection_record(edge) {
+            Some(old) if old.connection != connection => old,
+            _ => return,
+        };
+        self.connections[edge] = connection;
+        let gain_only =
+            |a: &Connection, b: &Connection| a.from_port == b.from_port && a.to_port == b.to_port;
+        if let (true, Some(EditCommand::EditConnection { old: first, new })) =
+            (self.redo_stack.is_empty(), self.undo_stack.last_mut())
+        {
+            if first.from == old.from
+                && first.to == old.to
+                && *new == old.connection
+                && gain_only(&first.connection, new)
+                && gain_only(new, &connection)
+            {
+                *new = connection;
+                return;
+            }
+        }
+        self.record(EditCommand::EditConnection {
+            old,
+            new: connection,
+        });
+    }
+
+    fn record(&mut self, command: EditCommand) {
+        // A live run holds node indices that structural edits invalidate.
+        self.stop();
+        self.edge_values.clear();
+        self.undo_stack.push(command);
+        self.redo_stack.clear();
+    }
+
+    // Applies `command` (or its inverse) and returns it, refreshed with the
+    // current component data, for the opposite stack.
+    fn apply(&mut self, command: EditCommand, inverse: bool) -> EditCommand {
+        self.stop();
+        self.edge_values.clear();
+        match command {
+            EditCommand::AddComponent(component) if inverse => {
+                match self.take_component(component.id) {
+                    Some((component, _)) => EditCommand::AddComponent(component),
+                    None => EditCommand::AddComponent(component),
+                }
+            }
+            EditCommand::AddComponent(component) => {
+                self.insert_component(component.clone());
+                EditCommand::AddComponent(component)
+            }
+            EditCommand::RemoveComponent {
+                component,
+                connections,
+            } if inverse => {
+                self.insert_component(component.clone());
+                for record in &connections {
+                    self.add_connection(record);
+                }
+                EditCommand::RemoveComponent {
+                    component,
+                    connections,
+                }
+            }
+            EditCommand::RemoveComponent { component, .. } => {
+                match self.take_component(component.id) {
+                    Some((component, connections)) => EditCommand::RemoveComponent {
+                        component,
+                        connections,
+                    },
+                    None => EditCommand::RemoveComponent {
+                        component,
+                        connections: Vec::new(),
+                    },
+                }
+            }
+            EditCommand::Connect(record) => {
+                if inverse {
+                    self.remove_connection(&record);
+                } else {
+                    self.add_connection(&record);
+                }
+                EditCommand::Connect(record)
+            }
+            EditCommand::Disconnect(records) => {
+                for record in &records {
+                    if inverse {
+                        self.add_connection(record);
+                    } else {
+                        self.remove_connection(record);
+                    }
+                }
+                EditCommand::Disconnect(records)
+            }
+            EditCommand::EditConnection { old, new } => {
+                let (current, target) = if inverse {
+                    (new, old.connection)
+                } else {
+                    (old.connection, new)
+                };
+                if let Some(edge) = self.find_connection(old.from, old.to, &current) {
+                    self.connections[edge] = target;
+                }
+                EditCommand::EditConnection { old, new }
+            }
+            EditCommand::Batch(commands) if inverse => {
+                let mut applied: Vec<EditCommand> = commands
+                    .into_iter()
+                    .rev()
+                    .map(|command| self.apply(command, true))
+                    .collect();
+                applied.reverse();
+                EditCommand::Batch(applied)
+            }
+            EditCommand::Batch(commands) => EditCommand::Batch(
+                commands
+                    .into_iter()
+                    .map(|command| self.apply(command, false))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some(command) = self.undo_stack.pop() {
+            let command = self.apply(command, true);
+            self.redo_stack.push(command);
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(command) = self.redo_stack.pop() {
+            let command = self.apply(command, false);
+            self.undo_stack.push(command);
+        }
+    }
+
+    fn to_model(&self) -> ModelFile {
+        let mut components: Vec<Component> = self.components.values().cloned().collect();
+        components.sort_by_key(|component| component.id);
+        let connections = self
+            .connections
+            .edge_indices()
+            .filter_map(|edge| self.connection_record(edge))
+            .collect();
+        let mut selected: Vec<usize> = self.selected_components.iter().copied().collect();
+        selected.sort();
+        ModelFile {
+            components,
+            connections,
+            view: Some(ViewState {
+                camera: self.camera,
+                selected,
+                plot_y: self.plot_y,
+            }),
+            metadata: None,
+        }
+    }
+
+    // The whole model, with any subsystems open for editing folded back in.
+    fn root_model(&self) -> ModelFile {
+        let mut model = self.to_model();
+        for parent in self.edit_stack.iter().rev() {
+            let mut outer = parent.model.clone();
+            if let Some(component) = outer
+                .components
+                .iter_mut()
+                .find(|component| component.id == parent.subsystem)
+            {
+                component.component_type = ComponentType::Subsystem { model };
+            }
+            model = outer;
+        }
+        model.metadata = Some(self.metadata.clone());
+        model
+    }
+
+    // Models are JSON unless the file ends in `.bin`, which selects the
+    // compact bincode encoding of the same ModelFile.
+    fn save_to_path(&self, path: &Path) -> io::Result<()> {
+        if is_binary_model(path) {
+            self.save_bin(path)
+        } else {
+            fs::write(path, serde_json::to_string_pretty(&self.root_model())?)
+        }
+    }
+
+    fn load_from_path(&mut self, path: &Path) -> io::Result<()> {
+        if is_binary_model(path) {
+            return self.load_bin(path);
+        }
+        let model: ModelFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.open_model(model);
+        Ok(())
+    }
+
+    fn save_bin(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, encode_bin(&self.root_model())?)
+    }
+
+    fn load_bin(&mut self, path: &Path) -> io::Result<()> {
+        let model = decode_bin(&fs::read(path)?)?;
+        self.open_model(model);
+        Ok(())
+    }
+
+    // Replaces the whole model, closing any open subsystems.
+    fn open_model(&mut self, mut model: ModelFile) {
+        self.metadata = model.metadata.take().unwrap_or_default();
+        self.edit_stack.clear();
+        self.load_model(model);
+    }
+
+    fn load_model(&mut self, model: ModelFile) {
+        self.stop();
+        self.autotune = None;
+        self.components.clear();
+        self.connections.clear();
+        self.component_states.clear();
+        self.simulation_data.clear();
+        self.simulation_time.clear();
+        self.xy_data.clear();
+        self.display_values.clear();
+        self.pinned = None;
+        self.sweep.clear();
+        self.sweep_run = None;
+        self.edge_values.clear();
+        self.probes.clear();
+        self.probe_data.clear();
+        self.selected_components.clear();
+        self.selected_connection = None;
+        self.context_menu = None;
+        self.raised.clear();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+
+        let mut nodes = HashMap::new();
+        for mut component in model.components {
+            component.is_dragging = false;
+            component.component_type.upgrade_legacy();
+            nodes.insert(component.id, self.connections.add_node(component.id));
+            self.components.insert(component.id, component);
+        }
+        for record in model.connections {
+            if let (Some(&from_idx), Some(&to_idx)) =
+                (nodes.get(&record.from), nodes.get(&record.to))
+            {
+                self.connections
+                    .add_edge(from_idx, to_idx, record.connection);
+            }
+        }
+        self.upgrade_sum_ports();
+        self.next_id = self.components.keys().max().map_or(0, |id| id + 1);
+        match model.view {
+            Some(view) => {
+                self.camera = view.camera;
+                self.plot_y = view.plot_y;
+                self.selected_components = view
+                    .selected
+                    .into_iter()
+                    .filter(|id| self.components.contains_key(id))
+                    .collect();
+            }
+            // Files saved before the view was stored are framed on first draw.
+            None => self.fit_pending = true,
+        }
+    }
+
+    // Sums used to take all wires on one port and apply the signs in wire
+    // order; spread such wires over one port per sign, in the same order.
+    fn upgrade_sum_ports(&mut self) {
+        let nodes: Vec<NodeIndex> = self.connections.node_indices().collect();
+        for node_idx in nodes {
+            let id = self.connections[node_idx];
+            let edges = self.incoming_edges(node_idx);
+            let legacy = edges.len() > 1
+                && edges
+                    .iter()
+                    .all(|edge| self.connections[*edge].to_port == 0);
+            let signs = match self.components.get_mut(&id).map(|c| &mut c.component_type) {
+                Some(ComponentType::Sum { signs }) if legacy => signs,
+                _ => continue,
+            };
+            let ports = edges.len().min(SUBSYSTEM_INPUTS.len());
+            if signs.len() < ports {
+                signs.resize(ports, 1.0);
+            }
+            for (port, edge) in edges.into_iter().enumerate().take(ports) {
+                self.connections[edge].to_port = port;
+            }
+        }
+    }
+
+    fn enter_subsystem(&mut self, id: usize) {
+        let model = match self.components.get(&id) {
+            Some(Component {
+                component_type: ComponentType::Subsystem { model },
+                ..
+            }) => model.clone(),
+            _ => return,
+        };
+        self.edit_stack.push(ParentLevel {
+            model: self.to_model(),
+            subsystem: id,
+            undo_stack: std::mem::take(&mut self.undo_stack),
+            redo_stack: std::mem::take(&mut self.redo_stack),
+        });
+        self.load_model(model);
+    }
+
+    fn leave_subsystem(&mut self) {
+        let parent = match self.edit_stack.pop() {
+            Some(parent) => parent,
+            None => return,
+        };
+        let inner = self.to_model();
+        self.load_model(parent.model);
+        self.undo_stack = parent.undo_stack;
+        self.redo_stack = parent.redo_stack;
+        if let Some(component) = self.components.get_mut(&parent.subsystem) {
+            component.component_type = ComponentType::Subsystem { model: inner };
+            // Drop wires to ports whose Inport or Outport was removed inside.
+            self.prune_port_edges(parent.subsystem);
+        }
+    }
+
+    // Removes wires attached to ports the component no longer has, as one
+    // undoable disconnect.
+    fn prune_port_edges(&mut self, id: usize) {
+        let component = match self.components.get(&id) {
+            Some(component) => component,
+            None => return,
+        };
+        let inputs = component.component_type.input_ports().len();
+        let outputs = component.component_type.output_ports().len();
+        let removed: Vec<ConnectionRecord> = self
+            .connections
+            .edge_indices()
+            .filter_map(|edge| self.connection_record(edge))
+            .filter(|record| {
+                (record.from == id && record.connection.from_port >= outputs)
+                    || (record.to == id && record.connection.to_port >= inputs)
+            })
+            .collect();
+        if removed.is_empty() {
+            return;
+        }
+        for record in &removed {
+            self.remove_connection(record);
+        }
+        self.selected_connection = None;
+        self.record(EditCommand::Disconnect(removed));
+    }
+
+    // Places each block one column right of its furthest upstream block,
+    // with scopes in the last column. Blocks are visited in reverse DFS
+    // postorder from the sources, so feedback wires point backwards in the
+    // order and are skipped.
+    fn auto_layout(&mut self) {
+        let mut starts: Vec<NodeIndex> = self.connections.node_indices().collect();
+        starts.sort_by_key(|&node_idx| {
+            let has_inputs = self
+                .connections
+                .edges_directed(node_idx, petgraph::Incoming)
+                .next()
+                .is_some();
+            (has_inputs, self.connections[node_idx])
+        });
+        let mut dfs = DfsPostOrder::empty(&self.connections);
+        let mut order = Vec::new();
+        for start in starts {
+            if !dfs.discovered.contains(start.index()) {
+                dfs.move_to(start);
+                while let Some(node_idx) = dfs.next(&self.connections) {
+                    order.push(node_idx);
+                }
+            }
+        }
+        order.reverse();
+
+        let mut layers: HashMap<usize, usize> = HashMap::new();
+        for &node_idx in &order {
+            let layer = self
+                .connections
+                .edges_directed(node_idx, petgraph::Incoming)
+                .filter_map(|edge| layers.get(&self.connections[edge.source()]))
+                .map(|layer| layer + 1)
+                .max()
+                .unwrap_or(0);
+            layers.insert(self.connections[node_idx], layer);
+        }
+        let last = layers.values().max().copied().unwrap_or(0);
+        for (id, layer) in layers.iter_mut() {
+            if self.components[id].component_type.is_sink() {
+                *layer = last;
+            }
+        }
+
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+        for &node_idx in &order {
+            let id = self.connections[node_idx];
+            let layer = layers[&id];
+            let row = rows.entry(layer).or_insert(0);
+            if let Some(component) = self.components.get_mut(&id) {
+                component.position = egui::pos2(layer as f32 * 150.0, *row as f32 * 80.0).into();
+            }
+            *row += 1;
+        }
+        self.fit_pending = true;
+    }
+
+    fn fit_to_content(&mut self, viewport: egui::Rect) {
+        self.camera = self
+            .components
+            .values()
+            .map(Component::rect)
+            .reduce(|a, b| a.union(b))
+            .map_or_else(Camera::default, |content| Camera::fit(content, viewport));
+    }
+
+    // The first block after `after` (by id, wrapping around) whose id, name,
+    // label or type matches `query`.
+    fn find_component(&self, query: &str, after: Option<usize>) -> Option<usize> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return None;
+        }
+        let mut ids: Vec<usize> = self
+            .components
+            .values()
+            .filter(|component| {
+                let description = component.component_type.describe();
+                let type_name = description.lines().next().unwrap_or_default();
+                query.parse() == Ok(component.id)
+                    || component.label().to_lowercase().contains(&query)
+                    || type_name.to_lowercase().contains(&query)
+            })
+            .map(|component| component.id)
+            .collect();
+        ids.sort();
+        ids.iter()
+            .copied()
+            .find(|&id| after.is_none_or(|after| id > after))
+            .or_else(|| ids.first().copied())
+    }
+
+    // Accumulated value of every error-integral block, in id order.
+    fn costs(&self) -> Vec<(usize, ErrorCost, f32)> {
+        let mut costs: Vec<(usize, ErrorCost, f32)> = self
+            .components
+            .values()
+            .filter_map(|component| {
+                match (
+                    &component.component_type,
+                    self.component_states.get(&component.id),
+                ) {
+                    (
+                        ComponentType::ErrorIntegral { mode },
+                        Some(ComponentState::Integrator { accumulator, .. }),
+                    ) => Some((component.id, *mode, *accumulator)),
+                    _ => None,
+                }
+            })
+            .collect();
+        costs.sort_by_key(|(id, ..)| *id);
+        costs
+    }
+
+    fn scope_traces(&self) -> Vec<(String, &[f32])> {
+        let mut ids: Vec<&usize> = self.simulation_data.keys().collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| {
+                let name = self
+                    .components
+                    .get(id)
+                    .and_then(|component| component.name.clone())
+                    .unwrap_or_else(|| format!("Scope {}", id));
+                (name, self.simulation_data[id].as_slice())
+            })
+            .collect()
+    }
+
+    fn probe_traces(&self) -> Vec<(String, Vec<[f64; 2]>)> {
+        self.probes
+            .iter()
+            .filter_map(|wire| {
+                let data = self.probe_data.get(wire)?;
+                let label = |port: PortRef| {
+                    self.components
+                        .get(&port.component)
+                        .map_or_else(|| port.component.to_string(), Component::label)
+                };
+                let name = format!("Probe {} → {}", label(wire.0), label(wire.1));
+                let points = data.iter().map(|[t, v]| [*t as f64, *v as f64]).collect();
+                Some((name, points))
+            })
+            .collect()
+    }
+
+    fn export_csv(&self, path: &Path) -> io::Result<()> {
+        let traces = self.scope_traces();
+        let mut csv = String::from("time");
+        for (name, _) in &traces {
+            csv.push(',');
+            csv.push_str(&csv_field(name));
+        }
+        csv.push('\n');
+
+        let rows = traces.iter().map(|(_, data)| data.len()).max().unwrap_or(0);
+        for i in 0..rows {
+            csv.push_str(&self.sample_time(i).to_string());
+            for (_, data) in &traces {
+                csv.push(',');
+                if let Some(value) = data.get(i) {
+                    csv.push_str(&value.to_string());
+                }
+            }
+            csv.push('\n');
+        }
+        fs::write(path, csv)
+    }
+
+    // Time of recorded sample `i`, extrapolated with the current step size
+    // past the end of the run.
+    fn sample_time(&self, i: usize) -> f32 {
+        self.simulation_time
+            .get(i)
+            .copied()
+            .unwrap_or(i as f32 * self.dt)
+    }
+
+    // The selected scope if there is one, otherwise the one being analysed.
+    fn focused_scope(&self) -> Option<usize> {
+        self.selected_component()
+            .filter(|id| self.simulation_data.contains_key(id))
+            .or(self.analysis_scope)
+            .filter(|id| self.simulation_data.contains_key(id))
+    }
+
+    // Tab-separated time and value columns, ready to paste into a spreadsheet.
+    fn scope_tsv(&self, id: usize) -> Option<String> {
+        let data = self.simulation_data.get(&id)?;
+        let name = self
+            .components
+            .get(&id)
+            .and_then(|component| component.name.clone())
+            .unwrap_or_else(|| format!("Scope {}", id));
+        let mut tsv = format!("time\t{}\n", name);
+        for (i, value) in data.iter().enumerate() {
+            tsv.push_str(&format!("{}\t{}\n", self.sample_time(i), value));
+        }
+        Some(tsv)
+    }
+
+    // Renders the diagram in world coordinates, matching the canvas colors.
+    fn export_svg(&self, path: &Path) -> io::Result<()> {
+        let bounds = self
+            .components
+            .values()
+            .map(Component::rect)
+            .reduce(|a, b| a.union(b))
+            .unwrap_or(egui::Rect::from_center_size(Pos2::ZERO, egui::Vec2::ZERO))
+            .expand(40.0);
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" width=\"{}\" height=\"{}\">\n",
+            bounds.left(),
+            bounds.top(),
+            bounds.width(),
+            bounds.height(),
+            bounds.width(),
+            bounds.height()
+        );
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            bounds.left(),
+            bounds.top(),
+            bounds.width(),
+            bounds.height(),
+            svg_color(egui::Color32::from_gray(27))
+        ));
+
+        let wire = svg_color(egui::Color32::LIGHT_GRAY);
+        for edge in self.connections.edge_indices() {
+            let record = match self.connection_record(edge) {
+                Some(record) => record,
+                None => continue,
+            };
+            let (from_port, to_port) = (record.connection.from_port, record.connection.to_port);
+            let (source, target) = (&self.components[&record.from], &self.components[&record.to]);
+            let route = wire_route(
+                source.port_pos(PortKind::Output, from_port),
+                target.port_pos(PortKind::Input, to_port),
+                source.rect(),
+                target.rect(),
+            );
+            let points: Vec<String> = route
+                .iter()
+                .map(|point| format!("{},{}", point.x, point.y))
+                .collect();
+            svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\"/>\n",
+                points.join(" "),
+                wire
+            ));
+            let last = route.len() - 1;
+            if let Some([a, b, c]) = arrowhead(route[last - 1], route[last], 1.0) {
+                svg.push_str(&format!(
+                    "<polygon points=\"{},{} {},{} {},{}\" fill=\"{}\"/>\n",
+                    a.x, a.y, b.x, b.y, c.x, c.y, wire
+                ));
+            }
+        }
+
+        let mut components: Vec<&Component> = self.components.values().collect();
+        components.sort_by_key(|component| component.id);
+        for component in components {
+            let rect = component.rect();
+            let (corner, label) = if component.injection_point {
+                (rect.width() / 2.0, "+".to_string())
+            } else {
+                (5.0, component.label())
+            };
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" fill=\"{}\"/>\n",
+                rect.left(),
+                rect.top(),
+                rect.width(),
+                rect.height(),
+                corner,
+                svg_color(component.component_type.color())
+            ));
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"14\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>\n",
+                rect.center().x,
+                rect.center().y,
+                svg_escape(&label)
+            ));
+            for port in component.ports() {
+                let pos = component.port_pos(port.kind, port.index);
+                svg.push_str(&format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>\n",
+                    pos.x,
+                    pos.y,
+                    PORT_RADIUS,
+                    svg_color(egui::Color32::DARK_GRAY)
+                ));
+            }
+        }
+        svg.push_str("</svg>\n");
+        fs::write(path, svg)
+    }
+
+    fn node_index(&self, component_id: usize) -> Option<NodeIndex> {
+        self.connections
+            .node_indices()
+            .find(|n| self.connections[*n] == component_id)
+    }
+
+    fn connect_components(
+        &mut self,
+        from: usize,
+        from_port: usize,
+        to: usize,
+        to_port: usize,
+    ) -> Result<(), &'static str> {
+        let (from_idx, to_idx) = match (self.node_index(from), self.node_index(to)) {
+            (Some(from_idx), Some(to_idx)) => (from_idx, to_idx),
+            _ => return Err("Unknown component"),
+        };
+        if from == to {
+            return Err("A block cannot be connected to itself");
+        }
+        let has_ports = from_port < self.components[&from].component_type.output_ports().len()
+            && to_port < self.components[&to].component_type.input_ports().len();
+        if !has_ports {
+            return Err("No such port");
+        }
+        let duplicate = self
+            .connections
+            .edges_connecting(from_idx, to_idx)
+            .any(|edge| edge.weight().from_port == from_port && edge.weight().to_port == to_port);
+        if duplicate {
+            return Err("Those ports are already connected");
+        }
+        let record = ConnectionRecord {
+            from,
+            to,
+            connection: Connection {
+                from_port,
+                to_port,
+                ..Connection::default()
+            },
+        };
+        self.add_connection(&record);
+        self.record(EditCommand::Connect(record));
+        Ok(())
+    }
+
+    fn port_at(&self, pos: Pos2, radius: f32) -> Option<PortRef> {
+        self.draw_order().into_iter().rev().find_map(|id| {
+            let component = &self.components[&id];
+            component
+                .ports()
+                .find(|port| component.port_pos(port.kind, port.index).distance(pos) < radius)
+        })
+    }
+
+    fn remove_component(&mut self, id: usize) {
+        if let Some((component, connections)) = self.take_component(id) {
+            self.record(EditCommand::RemoveComponent {
+                component,
+                connections,
+            });
+        }
+    }
+
+    // Removes one wire; others between the same pair of blocks, on other
+    // ports, are kept.
+    fn disconnect(&mut self, edge: EdgeIndex) {
+        if let Some(record) = self.connection_record(edge) {
+            self.connections.remove_edge(edge);
+            self.record(EditCommand::Disconnect(vec![record]));
+        }
+        self.selected_connection = None;
+    }
+
+    fn set_connection_gain(&mut self, edge: EdgeIndex, gain: f32) {
+        if let Some(&connection) = self.connections.edge_weight(edge) {
+            self.update_connection(edge, Connection { gain, ..connection });
+        }
+    }
+
+
+    fn reset_state(&mut self) {
+        self.component_states = self
+            .components
+            .iter()
+            .map(|(id, component)| {
+                let state = ComponentState::new(
+                    &component.component_type,
+                    self.dt,
+                    self.solver,
+                    self.total_time,
+                );
+                (*id, state)
+            })
+            .collect();
+    }
+
+    fn evaluation_order(&self) -> Result<Vec<NodeIndex>, SimError> {
+        // Wires into loop-breaking blocks are not needed within a step, so
+        // dropping them leaves a graph that must be acyclic. A disabled
+        // breaker passes its input straight through, so its wires stay.
+        let feedthrough = self.connections.filter_map(
+            |_, id| Some(*id),
+            |edge, connection| {
+                let (_, to) = self.connections.edge_endpoints(edge)?;
+                let target = self.components.get(&self.connections[to])?;
+                let breaks_loop = target.enabled && target.component_type.breaks_algebraic_loop();
+                (!breaks_loop).then_some(*connection)
+            },
+        );
+
+        toposort(&feedthrough, None).map_err(|_| {
+            let mut ids: Vec<usize> = tarjan_scc(&feedthrough)
+                .into_iter()
+                .filter(|scc| scc.len() > 1 || feedthrough.contains_edge(scc[0], scc[0]))
+                .flatten()
+                .map(|node_idx| feedthrough[node_idx])
+                .collect();
+            ids.sort();
+            SimError::AlgebraicLoop(ids)
+        })
+    }
+
+    fn validate(&self) -> Vec<ValidationError> {
+        let mut ids: Vec<&usize> = self.components.keys().collect();
+        ids.sort();
+        let mut errors = Vec::new();
+        for &id in ids {
+            let component_type = &self.components[&id].component_type;
+            let node_idx = match self.node_index(id) {
+                Some(node_idx) => node_idx,
+                None => continue,
+            };
+            let (inputs, outputs) = (
+                component_type.input_ports().len(),
+                component_type.output_ports().len(),
+            );
+            let incoming = self
+                .connections
+                .edges_directed(node_idx, petgraph::Incoming);
+            for edge in incoming {
+                if edge.weight().to_port >= inputs {
+                    errors.push(ValidationError::NoSuchPort {
+                        component: id,
+                        kind: PortKind::Input,
+                        port: edge.weight().to_port,
+                    });
+                }
+            }
+            let outgoing = self
+                .connections
+                .edges_directed(node_idx, petgraph::Outgoing);
+            for edge in outgoing {
+                if edge.weight().from_port >= outputs {
+                    errors.push(ValidationError::NoSuchPort {
+                        component: id,
+                        kind: PortKind::Output,
+                        port: edge.weight().from_port,
+                    });
+                }
+            }
+            if self.unconnected_sink(id) {
+                errors.push(ValidationError::UnconnectedSink { component: id });
+                continue;
+            }
+            for (port, name) in component_type.input_ports().into_iter().enumerate() {
+                let connected = self
+                    .connections
+                    .edges_directed(node_idx, petgraph::Incoming)
+                    .any(|edge| edge.weight().to_port == port);
+                if !connected {
+                    errors.push(ValidationError::UnconnectedInput {
+                        component: id,
+                        port: name,
+                    });
+                }
+            }
+            let has_outputs = !component_type.output_ports().is_empty();
+            let used = self
+                .connections
+                .edges_directed(node_idx, petgraph::Outgoing)
+                .next()
+                .is_some();
+            if has_outputs && !used {
+                errors.push(ValidationError::DanglingOutput { component: id });
+            }
+            if let ComponentType::ZeroOrderHold { sample_every } = component_type {
+                let period = *sample_every as f32 * self.dt;
+                if period > self.total_time {
+                    errors.push(ValidationError::SamplePeriodTooLong { component: id });
+                }
+                // Frequencies are taken from the periodic sources upstream,
+                // ignoring harmonics added by nonlinear blocks on the way.
+                let graph = Reversed(&self.connections);
+                let mut upstream = Dfs::new(graph, node_idx);
+                let mut signal_hz = 0.0_f32;
+                while let Some(node) = upstream.next(graph) {
+                    let source = &self.components[&self.connections[node]].component_type;
+                    if let Some(freq_hz) = source.source_frequency() {
+                        signal_hz = signal_hz.max(freq_hz);
+                    }
+                }
+                let sample_hz = 1.0 / period;
+                // Close to Nyquist already distorts the held signal badly.
+                if signal_hz > 0.0 && signal_hz >= 0.8 * sample_hz / 2.0 {
+                    errors.push(ValidationError::Undersampled {
+                        component: id,
+                        sample_hz,
+                        signal_hz,
+                    });
+                }
+            }
+            if let ComponentType::Subsystem { model } = component_type {
+                let mut inner = SimulatorApp::new();
+                inner.load_model(model.clone());
+                if let Some(error) = inner
+                    .validate()
+                    .into_iter()
+                    .find(ValidationError::blocks_run)
+                {
+                    errors.push(ValidationError::InvalidSubsystem {
+                        component: id,
+                        error: Box::new(error),
+                    });
+                }
+            }
+        }
+        if let Err(SimError::AlgebraicLoop(ids)) = self.evaluation_order() {
+            errors.push(ValidationError::AlgebraicLoop(ids));
+        }
+        errors
+    }
+
+    fn unconnected_sink(&self, id: usize) -> bool {
+        let is_sink = self
+            .components
+            .get(&id)
+            .is_some_and(|component| component.component_type.is_sink());
+        is_sink
+            && self.node_index(id).is_some_and(|node_idx| {
+                self.connections
+                    .edges_directed(node_idx, petgraph::Incoming)
+                    .next()
+                    .is_none()
+            })
+    }
+
+    fn unconnected_sinks(&self) -> HashSet<NodeIndex> {
+        self.connections
+            .node_indices()
+            .filter(|&node_idx| {
+                let id = self.connections[node_idx];
+                self.components
+                    .get(&id)
+                    .is_some_and(|component| component.component_type.is_sink())
+                    && self
+                        .connections
+                        .edges_directed(node_idx, petgraph::Incoming)
+                        .next()
+                        .is_none()
+            })
+            .collect()
+    }
+
+    fn check_validation(&mut self) -> bool {
+        self.validation = self.validate();
+        let ok = !self.validation.iter().any(ValidationError::blocks_run);
+        self.last_error = (!ok).then(|| "Fix the validation errors before running".to_string());
+        ok
+    }
+
+    fn run_validated(&mut self) {
+        self.stop();
+        if !self.check_validation() {
+            return;
+        }
+        match self.start_run() {
+            Ok(run) => {
+                self.run = Some(run);
+                self.running_to_end = true;
+            }
+            Err(err) => self.last_error = Some(err.to_string()),
+        }
+    }
+
+    // Advances the run started by `run_validated` for at most `budget`.
+    fn run_for(&mut self, budget: Duration) {
+        let started = Instant::now();
+        match self.run.take() {
+            Some(mut run) => {
+                let mut result = Ok(());
+                while result.is_ok() && !run.finished() && started.elapsed() < budget {
+                    result = self.advance(&mut run);
+                }
+                self.record_edge_values(&run);
+                self.running_to_end = !run.finished();
+                self.run = Some(run);
+                if let Err(err) = result {
+                    self.stop();
+                    self.last_error = Some(err.to_string());
+                }
+            }
+            None => self.running_to_end = false,
+        }
+    }
+
+    // Advances the live run by `steps`, starting a new one if needed.
+    fn step_live(&mut self, steps: usize) {
+        if self.run.is_none() {
+            if !self.check_validation() {
+                self.playing = false;
+                return;
+            }
+            match self.start_run() {
+                Ok(run) => self.run = Some(run),
+                Err(err) => {
+                    self.last_error = Some(err.to_string());
+                    self.playing = false;
+                    return;
+                }
+            }
+        }
+        if let Some(mut run) = self.run.take() {
+            let mut result = Ok(());
+            for _ in 0..steps {
+                if run.finished() || result.is_err() {
+                    break;
+                }
+                result = self.advance(&mut run);
+            }
+            self.record_edge_values(&run);
+            if run.finished() {
+                self.playing = false;
+            }
+            self.run = Some(run);
+            if let Err(err) = result {
+                self.stop();
+                self.last_error = Some(err.to_string());
+            }
+        }
+    }
+
+    // Ends the run, keeping whatever it has recorded so far.
+    fn stop(&mut self) {
+        self.run = None;
+        self.playing = false;
+        self.running_to_end = false;
+    }
+
+    fn start_run(&mut self) -> Result<Run, SimError> {
+        if self.components.is_empty() {
+            return Err(SimError::EmptyGraph);
+        }
+        if !(self.dt > 0.0 && self.dt.is_finite()) {
+            return Err(SimError::InvalidTimeStep(self.dt));
+        }
+        for error in self.validate() {
+            if let ValidationError::UnconnectedInput { component, port } = error {
+                return Err(SimError::MissingInput { component, port });
+            }
+        }
+        self.reset_state();
+        self.clear_plot();
+        self.edge_values.clear();
+        Ok(Run {
+            order: self.evaluation_order()?,
+            step: 0,
+            steps: (self.total_time / self.dt).round() as usize,
+            outputs: HashMap::new(),
+            unconnected_sinks: self.unconnected_sinks(),
+        })
+    }
+
+    fn simulate(&mut self) -> Result<(), SimError> {
+        let mut run = self.start_run()?;
+        let mut result = Ok(());
+        while result.is_ok() && !run.finished() {
+            result = self.advance(&mut run);
+        }
+        self.record_edge_values(&run);
+        result
+    }
+
+    // Samples and their times are cleared together so that a live run keeps
+    // them aligned as it appends.
+    fn clear_plot(&mut self) {
+        self.simulation_data.clear();
+        self.simulation_time.clear();
+        self.xy_data.clear();
+        self.display_values.clear();
+        self.probe_data.clear();
+    }
+
+    fn record_edge_values(&mut self, run: &Run) {
+        self.edge_values = self
+            .connections
+            .edge_indices()
+            .map(|edge| (edge, self.edge_value(edge, &run.outputs)))
+            .collect();
+    }
+
+    // Evaluates one time step and appends to the scope buffers, stopping at
+    // the first output that diverges.
+    fn advance(&mut self, run: &mut Run) -> Result<(), SimError> {
+        let time_step = self.dt;
+        let solver = self.solver;
+        let duration = self.total_time;
+        let bound = self.divergence_bound;
+        let step = run.step;
+        let component_outputs = &mut run.outputs;
+        if self.logging {
+            println!("Simulation step {}", step);
+        }
+        let t = step as f32 * time_step;
+        self.simulation_time.push(t);
+
+        for &node_idx in &run.order {
+            let component_id = self.connections[node_idx];
+
+            if run.unconnected_sinks.contains(&node_idx) {
+                continue;
+            }
+            if let Some(component) = self.components.get(&component_id) {
+                let inputs = self.get_input_values(node_idx, component_outputs);
+                let input_value = self.get_input_value(node_idx, component_outputs);
+                let port_signals: Vec<Signal> = (0..component.component_type.input_ports().len())
+                    .map(|port| self.get_port_signal(node_idx, port, component_outputs))
+                    .collect();
+                let port_values: Vec<f32> = port_signals.iter().map(Signal::scalar).collect();
+                // A bypassed block passes its first input straight through, or
+                // outputs zero without one; a bypassed sink records nothing.
+                if !component.enabled {
+                    if !component.component_type.output_ports().is_empty() {
+                        let output = port_signals.first().cloned();
+                        component_outputs
+                            .insert((component_id, 0), output.unwrap_or(Signal::Scalar(0.0)));
+                    }
+                    continue;
+                }
+                let state = self
+                    .component_states
+                    .entry(component_id)
+                    .or_insert_with(|| {
+                        ComponentState::new(&component.component_type, time_step, solver, duration)
+                    });
+
+                let output = match (&component.component_type, state) {
+                    (ComponentType::Step, _) => 1.0,
+                    (ComponentType::Constant { value }, _) => *value,
+                    (
+                        ComponentType::Sine {
+                            amplitude,
+                            freq_hz,
+                            phase,
+                        },
+                        _,
+                    ) => *amplitude * (2.0 * std::f32::consts::PI * *freq_hz * t + *phase).sin(),
+                    (ComponentType::MultiSine { components }, _) => components
+                        .iter()
+                        .map(|(amplitude, freq_hz, phase)| {
+                            amplitude * (2.0 * std::f32::consts::PI * freq_hz * t + phase).sin()
+                        })
+                        .sum(),
+                    (ComponentType::Ramp { slope, start_time }, _) => {
+                        if t >= *start_time {
+                            *slope * (t - *start_time)
+                        } else {
+                            0.0
+                        }
+                    }
+                    (ComponentType::Clock, _) => t,
+                    (
+                        ComponentType::Pulse {
+                            amplitude,
+                            period,
+                            duty,
+                        },
+                        _,
+                    ) => {
+                        if *period > 0.0 && (t / *period).fract() < *duty {
+                            *amplitude
+                        } else {
+                            0.0
+                        }
+                    }
+                    (ComponentType::Noise { std_dev, .. }, ComponentState::Noise(rng)) => {
+                        *std_dev * rng.next_gaussian()
+                    }
+                    (ComponentType::TransferFunction, ComponentState::Filter { output }) => {
+                        let alpha = 0.1;
+                        *output += alpha * (input_value - *output);
+                        *output
+                    }
+                    // Backward Euler, so the cutoff stays put when dt changes.
+                    (ComponentType::LowPass { tau }, ComponentState::Filter { output }) => {
+                        let alpha = time_step / (*tau + time_step);
+                        *output += alpha * (input_value - *output);
+                        *output
+                    }
+                    (ComponentType::Scope, _) => {
+                        self.simulation_data
+                            .entry(component_id)
+                            .or_default()
+                            .push(input_value);
+                        continue;
+                    }
+                    (ComponentType::Terminator, _) => continue,
+                    (ComponentType::NumericDisplay { .. }, _) => {
+                        self.display_values.insert(component_id, input_value);
+                        continue;
+                    }
+                    (ComponentType::XYScope, _) => {
+                        self.xy_data
+                            .entry(component_id)
+                            .or_default()
+                            .push([port_values[0], port_values[1]]);
+                        continue;
+                    }
+                    (ComponentType::Delay { steps: 0, .. }, _) => input_value,
+                    // Counted from the newest sample, so lowering `steps` during a
+                    // run takes effect at once.
+                    (ComponentType::Delay { steps, initial }, ComponentState::Delay { buffer }) => {
+                        if buffer.len() >= *steps {
+                            buffer[buffer.len() - *steps]
+                        } else {
+                            *initial
+                        }
+                    }
+                    (ComponentType::Difference, ComponentState::PreviousInput(prev)) => {
+                        let prev_value = prev.replace(input_value).unwrap_or(input_value);
+                        input_value - prev_value
+                    }
+                    // Filtered: backward Euler of y' + N y = N u', stable for any N and dt.
+                    (
+                        ComponentType::Derivative { filtered, n },
+                        ComponentState::Derivative { prev_input, output },
+                    ) => {
+                        let prev_value = prev_input.replace(input_value).unwrap_or(input_value);
+                        *output = if *filtered {
+                            (*output + *n * (input_value - prev_value)) / (1.0 + *n * time_step)
+                        } else {
+                            (input_value - prev_value) / time_step
+                        };
+                        *output
+                    }
+                    (
+                        ComponentType::DiscreteIntegrator
+                        | ComponentType::IntegratorIC { .. }
+                        | ComponentType::ErrorIntegral { .. },
+                        ComponentState::Integrator { accumulator, .. },
+                    ) => *accumulator,
+                    (
+                        ComponentType::PIDController {
+                            kp,
+                            ki,
+                            kd,
+                            output_limits,
+                        },
+                        ComponentState::Pid {
+                            prev_error,
+                            integral,
+                        },
+                    ) => {
+                        let error = port_values[0] - port_values[1];
+                        let derivative = (error - *prev_error) / time_step;
+                        *prev_error = error;
+                        let candidate_integral = *integral + error * time_step;
+                        let output = *kp * error + *ki * candidate_integral + *kd * derivative;
+                        match output_limits {
+                            Some((min, max)) => {
+                                let clamped = output.max(*min).min(*max);
+                                // Conditional integration: stop accumulating
+                                // while saturated unless the error unwinds it.
+                                let unwinding = (output > *max && *ki * error < 0.0)
+                                    || (output < *min && *ki * error > 0.0);
+                                if clamped == output || unwinding {
+                                    *integral = candidate_integral;
+                                }
+                                clamped
+                            }
+                            None => {
+                                *integral = candidate_integral;
+                                output
+                            }
+                        }
+                    }
+                    // Last step's input; the new one is latched after the step.
+                    (ComponentType::Memory { .. }, ComponentState::Hold { value }) => *value,
+                    (
+                        ComponentType::TransferFunctionCoeffs { .. }
+                        | ComponentType::DiscreteTransferFunction { .. },
+                        ComponentState::LinearFilter {
+                            b,
+                            a,
+                            inputs,
+                            outputs,
+                        },
+                    ) => filter_step(b, a, inputs, outputs, input_value),
+                    (
+                        ComponentType::TransferFunctionCoeffs { .. }
+                        | ComponentType::StateSpace { .. },
+                        ComponentState::StateSpace { c, d, x, .. },
+                    ) => {
+                        c.iter().zip(x.iter()).map(|(ci, xi)| ci * xi).sum::<f32>()
+                            + *d * input_value
+                    }
+                    (ComponentType::Gain { k }, _) => input_value * *k,
+                    (ComponentType::Saturation { min, max }, _) => input_value.max(*min).min(*max),
+                    (
+                        ComponentType::LookupTable {
+                            breakpoints,
+                            values,
+                        },
+                        _,
+                    ) => lookup(breakpoints, values, input_value),
+                    (
+                        ComponentType::Relay {
+                            on_threshold,
+                            off_threshold,
+                            on_value,
+                            off_value,
+                        },
+                        ComponentState::Relay { on },
+                    ) => {
+                        if input_value >= *on_threshold {
+                            *on = true;
+                        } else if input_value <= *off_threshold {
+                            *on = false;
+                        }
+                        if *on {
+                            *on_value
+                        } else {
+                            *off_value
+                        }
+                    }
+                    (
+                        ComponentType::TransportDelay { .. },
+                        ComponentState::TransportDelay { buffer, samples },
+                    ) => {
+                        let whole = samples.floor() as usize;
+                        let fraction = *samples - whole as f32;
+                        let newer = buffer[buffer.len() - whole];
+                        let older = buffer[buffer.len() - whole - 1];
+                        newer + fraction * (older - newer)
+                    }
+                    (ComponentType::Quantizer { step }, _) if *step > 0.0 => {
+                        (input_value / *step).round() * *step
+                    }
+                    (ComponentType::Quantizer { .. }, _) => input_value,
+                    (ComponentType::Abs, _) => input_value.abs(),
+                    (ComponentType::Sign, _) => {
+                        if input_value == 0.0 {
+                            0.0
+                        } else {
+                            input_value.signum()
+                        }
+                    }
+                    (ComponentType::Switch { threshold }, _) => {
+                        if port_values[1] >= *threshold {
+                            port_values[0]
+                        } else {
+                            port_values[2]
+                        }
+                    }
+                    (
+                        ComponentType::RateLimiter { rising, falling },
+                        ComponentState::PreviousOutput(prev),
+                    ) => {
+                        *prev +=
+                            (input_value - *prev).clamp(-*falling * time_step, *rising * time_step);
+                        *prev
+                    }
+                    (
+                        ComponentType::ZeroOrderHold { sample_every },
+                        ComponentState::Hold { value },
+                    ) => {
+                        if step.is_multiple_of((*sample_every).max(1)) {
+                            *value = input_value;
+                        }
+                        *value
+                    }
+                    (ComponentType::MinMax { .. }, _) if inputs.is_empty() => 0.0,
+                    (ComponentType::MinMax { mode }, _) => {
+                        let values = inputs.iter().copied();
+                        match mode {
+                            MinOrMax::Min => values.fold(f32::INFINITY, f32::min),
+                            MinOrMax::Max => values.fold(f32::NEG_INFINITY, f32::max),
+                        }
+                    }
+                    (ComponentType::Product, _) if inputs.is_empty() => 0.0,
+                    (ComponentType::Product, _) => inputs.iter().product(),
+                    (ComponentType::Sum { signs }, _) => port_values
+                        .iter()
+                        .zip(signs)
+                        .map(|(value, sign)| value * sign)
+                        .sum(),
+                    (ComponentType::Inport { index }, _) => {
+                        self.inport_values.get(*index).copied().unwrap_or(0.0)
+                    }
+                    (ComponentType::Outport { index }, _) => {
+                        if let Some(value) = self.outport_values.get_mut(*index) {
+                            *value = input_value;
+                        }
+                        continue;
+                    }
+                    (ComponentType::Subsystem { model }, ComponentState::Subsystem(inner)) => {
+                        let outputs = inner.evaluate(port_values, model.boundary_ports().1);
+                        for (port, value) in outputs.iter().enumerate().skip(1) {
+                            component_outputs.insert((component_id, port), Signal::Scalar(*value));
+                        }
+                        outputs.first().copied().unwrap_or(0.0)
+                    }
+                    (ComponentType::Mux { .. }, _) => {
+                        component_outputs.insert((component_id, 0), Signal::Vector(port_values));
+                        check_divergence(component_outputs, component_id, 1, step, bound)?;
+                        continue;
+                    }
+                    (ComponentType::Demux { width }, _) => {
+                        for port in 1..*width {
+                            let value = port_signals[0].element(port);
+                            component_outputs.insert((component_id, port), Signal::Scalar(value));
+                        }
+                        port_signals[0].element(0)
+                    }
+                    (component_type, state) => {
+                        unreachable!("state {:?} does not belong to {:?}", state, component_type)
+                    }
+                };
+
+                component_outputs.insert((component_id, 0), Signal::Scalar(output));
+                let ports = component.component_type.output_ports().len();
+                check_divergence(component_outputs, component_id, ports, step, bound)?;
+                if self.logging {
+                    println!(
+                        "Component ID {} ({}) output: {}",
+                        component_id,
+                        component.component_type.label(),
+                        output
+                    );
+                }
+            }
+        }
+
+        for &node_idx in &run.order {
+            let component_id = self.connections[node_idx];
+            if !self
+                .components
+                .get(&component_id)
+                .is_some_and(|c| c.enabled)
+            {
+                continue;
+            }
+            let input_value = self.get_input_value(node_idx, component_outputs);
+            let reset_input = match self.components.get(&component_id) {
+                Some(Component {
+                    component_type: ComponentType::IntegratorIC { reset: Some(_), .. },
+                    ..
+                }) => Some((
+                    self.get_port_input(node_idx, 0, component_outputs),
+                    self.get_port_input(node_idx, 1, component_outputs),
+                )),
+                _ => None,
+            };
+            match (
+                self.components
+                    .get(&component_id)
+                    .map(|component| &component.component_type),
+                self.component_states.get_mut(&component_id),
+            ) {
+                (
+                    Some(ComponentType::Delay { steps, .. }),
+                    Some(ComponentState::Delay { buffer }),
+                ) => {
+                    buffer.push_back(input_value);
+                    let excess = buffer.len().saturating_sub(*steps);
+                    buffer.drain(..excess);
+                }
+                (Some(ComponentType::Memory { .. }), Some(ComponentState::Hold { value })) => {
+                    *value = input_value;
+                }
+                (
+                    Some(ComponentType::TransportDelay { .. }),
+                    Some(ComponentState::TransportDelay { buffer, .. }),
+                ) => {
+                    buffer.pop_front();
+                    buffer.push_back(input_value);
+                }
+                (
+                    Some(ComponentType::DiscreteIntegrator),
+                    Some(ComponentState::Integrator {
+                        accumulator,
+                        prev_input,
+                        ..
+                    }),
+                ) => integrate(accumulator, prev_input, input_value, time_step, solver),
+                (
+                    Some(ComponentType::ErrorIntegral { mode }),
+                    Some(ComponentState::Integrator { accumulator, .. }),
+                ) => *accumulator += mode.weight(input_value, t) * time_step,
+                (
+                    Some(ComponentType::IntegratorIC { reset, .. }),
+                    Some(ComponentState::Integrator {
+                        accumulator,
+                        prev_input,
+                        prev_reset,
+                    }),
+                ) => match (reset, reset_input) {
+                    (Some(threshold), Some((input, reset_value))) => {
+                        let rising = prev_reset.is_some_and(|prev| prev < *threshold)
+                            && reset_value >= *threshold;
+                        *prev_reset = Some(reset_value);
+                        if rising {
+                            *accumulator = 0.0;
+                            *prev_input = None;
+                        } else {
+                            integrate(accumulator, prev_input, input, time_step, solver);
+                        }
+                    }
+                    _ => integrate(accumulator, prev_input, input_value, time_step, solver),
+                },
+                (
+                    Some(
+                        ComponentType::TransferFunctionCoeffs { .. }
+                        | ComponentType::StateSpace { .. },
+                    ),
+                    Some(ComponentState::StateSpace {
+                        a,
+                        b,
+                        x,
+                        prev_input,
+                        ..
+                    }),
+                ) => {
+                    let derivative = |x: &[f32], u: f32| -> Vec<f32> {
+                        a.iter()
+                            .zip(b.iter())
+                            .map(|(row, bi)| {
+                                row.iter().zip(x).map(|(aij, xj)| aij * xj).sum::<f32>() + bi * u
+                            })
+                            .collect()
+                    };
+                    match solver {
+                        SolverMethod::Euler => {
+                            let dx = derivative(x, input_value);
+                            for (xi, dxi) in x.iter_mut().zip(dx) {
+                                *xi += dxi * time_step;
+                            }
+                        }
+                        SolverMethod::Rk4 => {
+                            let u = extrapolated_input(input_value, *prev_input, time_step);
+                            rk4_step(x, time_step, |tau, x| derivative(x, u(tau)));
+                        }
+                    }
+                    *prev_input = Some(input_value);
+                }
+                _ => {}
+            }
+        }
+        let probed: Vec<((PortRef, PortRef), f32)> = self
+            .probes
+            .iter()
+            .filter_map(|wire| Some((*wire, self.edge_value(self.wire_edge(*wire)?, &run.outputs))))
+            .collect();
+        for (wire, value) in probed {
+            self.probe_data.entry(wire).or_default().push([t, value]);
+        }
+        run.step += 1;
+        Ok(())
+    }
+    fn incoming_edges(&self, node_idx: NodeIndex) -> Vec<EdgeIndex> {
+        let mut edges: Vec<EdgeIndex> = self
+            .connections
+            .edges_directed(node_idx, petgraph::Incoming)
+            .map(|edge| edge.id())
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    fn get_input_values(
+        &self,
+        node_idx: NodeIndex,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> Vec<f32> {
+        self.incoming_edges(node_idx)
+            .into_iter()
+            .map(|edge| self.edge_value(edge, component_outputs))
+            .collect()
+    }
+
+    fn edge_value(
+        &self,
+        edge: EdgeIndex,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> f32 {
+        self.edge_signal(edge, component_outputs).scalar()
+    }
+
+    fn edge_signal(
+        &self,
+        edge: EdgeIndex,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> Signal {
+        let (source, _) = self.connections.edge_endpoints(edge).unwrap();
+        let connection = self.connections[edge];
+        component_outputs
+            .get(&(self.connections[source], connection.from_port))
+            .map_or(Signal::Scalar(0.0), |signal| signal.scaled(connection.gain))
+    }
+
+    fn get_port_input(
+        &self,
+        node_idx: NodeIndex,
+        port: usize,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> f32 {
+        self.get_port_signal(node_idx, port, component_outputs)
+            .scalar()
+    }
+
+    fn get_port_signal(
+        &self,
+        node_idx: NodeIndex,
+        port: usize,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> Signal {
+        self.incoming_edges(node_idx)
+            .into_iter()
+            .filter(|edge| self.connections[*edge].to_port == port)
+            .map(|edge| self.edge_signal(edge, component_outputs))
+            .fold(Signal::Scalar(0.0), |sum, signal| sum.add(&signal))
+    }
+
+    fn get_input_value(
+        &self,
+        node_idx: NodeIndex,
+        component_outputs: &HashMap<(usize, usize), Signal>,
+    ) -> f32 {
+        let mut input_sum = 0.0;
+
+        for edge in self
+            .connections
+            .edges_directed(node_idx, petgraph::Incoming)
+        {
+            let key = (self.connections[edge.source()], edge.weight().from_port);
+            if let Some(output) = component_outputs.get(&key) {
+                input_sum += output.scalar() * edge.weight().gain;
+            }
+        }
+
+        if self.logging {
+            println!(
+                "Component ID {} received input value: {}",
+                self.connections[node_idx], input_sum
+            );
+        }
+
+        input_sum
+    }
+}
+
+impl SimulatorApp {
+    fn step_response_view(&mut self, ui: &mut egui::Ui) {
+        let mut scopes: Vec<usize> = self.simulation_data.keys().copied().collect();
+        if scopes.is_empty() {
+            return;
+        }
+        scopes.sort();
+        let scope = match self.analysis_scope {
+            Some(id) if scopes.contains(&id) => id,
+            _ => scopes[0],
+        };
+        self.analysis_scope = Some(scope);
+
+        ui.separator();
+        ui.heading("Step Response");
+        egui::ComboBox::from_label("Scope")
+            .selected_text(format!("Scope {}", scope))
+            .show_ui(ui, |ui| {
+                for id in scopes {
+                    let name = format!("Scope {}", id);
+                    ui.selectable_value(&mut self.analysis_scope, Some(id), name);
+                }
+            });
+
+        let sample_dt = self.sample_time(1) - self.sample_time(0);
+        let metrics = match step_metrics(&self.simulation_data[&scope], sample_dt) {
+            Some(metrics) => metrics,
+            None => {
+                ui.label("No step in response");
+                return;
+            }
+        };
+        let seconds = |t: Option<f32>| t.map_or("—".to_owned(), |t| format!("{:.3} s", t));
+        egui::Grid::new("step_metrics").show(ui, |ui| {
+            ui.label("Rise time (10–90%)");
+            ui.label(seconds(metrics.rise_time));
+            ui.end_row();
+            ui.label("Overshoot");
+            ui.label(format!("{:.1} %", metrics.overshoot_percent));
+            ui.end_row();
+            ui.label("Peak time");
+            ui.label(seconds(Some(metrics.peak_time)));
+            ui.end_row();
+            ui.label("Settling time (±2%)");
+            match metrics.settling_time {
+                Some(t) => ui.label(format!("{:.3} s", t)),
+                None => ui.label("did not settle"),
+            };
+            ui.end_row();
+        });
+    }
+
+    fn spectrum_view(&self, ui: &mut egui::Ui) {
+        let scope = match self.analysis_scope {
+            Some(id) if self.simulation_data.contains_key(&id) => id,
+            _ => return,
+        };
+        let sample_dt = self.sample_time(1) - self.sample_time(0);
+        let spectrum = amplitude_spectrum(&self.simulation_data[&scope], sample_dt);
+
+        ui.separator();
+        ui.heading("Spectrum");
+        Plot::new("spectrum_plot")
+            .height(180.0)
+            .include_y(0.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(PlotPoints::from(spectrum)).name("Amplitude"));
+            });
+        ui.vertical_centered(|ui| ui.small("Frequency (Hz)"));
+    }
+
+    fn display_view(&self, ui: &mut egui::Ui) {
+        let mut displays: Vec<(usize, usize)> = self
+            .components
+            .values()
+            .filter_map(|component| match component.component_type {
+                ComponentType::NumericDisplay { decimals } => Some((component.id, decimals)),
+                _ => None,
+            })
+            .collect();
+        if displays.is_empty() {
+            return;
+        }
+        displays.sort();
+        ui.separator();
+        ui.heading("Displays");
+        for (id, decimals) in displays {
+            let value = match self.display_values.get(&id) {
+                Some(value) => format!("{:.*}", decimals, value),
+                None => "—".to_string(),
+            };
+            ui.horizontal(|ui| {
+                ui.label(self.components[&id].label());
+                ui.label(egui::RichText::new(value).monospace().size(24.0));
+            });
+        }
+    }
+
+    fn phase_plane_view(&self, ui: &mut egui::Ui) {
+        if self.xy_data.is_empty() {
+            return;
+        }
+        let mut scopes: Vec<&usize> = self.xy_data.keys().collect();
+        scopes.sort();
+        ui.separator();
+        ui.heading("Phase Plane");
+        Plot::new("xy_plot")
+            .height(220.0)
+            .data_aspect(1.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for (i, id) in scopes.into_iter().enumerate() {
+                    let points: Vec<[f64; 2]> = self.xy_data[id]
+                        .iter()
+                        .map(|[x, y]| [*x as f64, *y as f64])
+                        .collect();
+                    let name = self
+                        .components
+                        .get(id)
+                        .map_or_else(|| format!("XY Scope {}", id), Component::label);
+                    plot_ui.line(
+                        Line::new(PlotPoints::from(points))
+                            .color(TRACE_COLORS[i % TRACE_COLORS.len()])
+                            .name(name),
+                    );
+                }
+            });
+    }
+
+    // A separate app with this model and run settings, for runs that must not
+    // disturb the editor's own results.
+    fn run_copy(&self) -> SimulatorApp {
+        let mut app = SimulatorApp::new();
+        app.load_model(self.to_model());
+        app.dt = self.dt;
+        app.total_time = self.total_time;
+        app.solver = self.solver;
+        app.divergence_bound = self.divergence_bound;
+        app
+    }
+
+    // Runs the model on a worker thread once per sweep value of a parameter
+    // of block `id`, keeping every scope trace for the plot overlay.
+    fn start_sweep(&mut self, id: usize) {
+        let settings = self.sweep_settings;
+        let values = settings.values();
+        let base = self.run_copy();
+        let (sender, receiver) = mpsc::channel();
+        self.sweep_run = Some(SweepRun {
+            receiver,
+            runs: 0,
+            total: values.len(),
+        });
+        thread::spawn(move || {
+            let mut traces = Vec::new();
+            for (runs, value) in values.into_iter().enumerate() {
+                let mut app = base.run_copy();
+                let name = match app.components.get_mut(&id).and_then(|component| {
+                    let mut parameters = component.component_type.parameters_mut();
+                    (settings.parameter < parameters.len())
+                        .then(|| parameters.swap_remove(settings.parameter))
+                }) {
+                    Some((name, parameter)) => {
+                        *parameter = value;
+                        name
+                    }
+                    None => {
+                        let error = "The block has no such parameter".to_string();
+                        let _ = sender.send(SweepMessage::Done(Err(error)));
+                        return;
+                    }
+                };
+                if let Err(err) = app.simulate() {
+                    let error = format!("Sweep failed at {} = {}: {}", name, value, err);
+                    let _ = sender.send(SweepMessage::Done(Err(error)));
+                    return;
+                }
+                for (scope, data) in app.scope_traces() {
+                    let points = data
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &y)| [app.sample_time(i) as f64, y as f64])
+                        .collect();
+                    traces.push((format!("{}, {} = {}", scope, name, value), points));
+                }
+                let _ = sender.send(SweepMessage::Progress { runs: runs + 1 });
+            }
+            let _ = sender.send(SweepMessage::Done(Ok(traces)));
+        });
+    }
+
+    fn poll_sweep(&mut self) {
+        let sweep_run = match &mut self.sweep_run {
+            Some(sweep_run) => sweep_run,
+            None => return,
+        };
+        let mut result = None;
+        for message in sweep_run.receiver.try_iter() {
+            match message {
+                SweepMessage::Progress { runs } => sweep_run.runs = runs,
+                SweepMessage::Done(done) => result = Some(done),
+            }
+        }
+        match result {
+            Some(Ok(traces)) => {
+                self.sweep_run = None;
+                self.sweep = traces;
+            }
+            Some(Err(err)) => {
+                self.sweep_run = None;
+                self.last_error = Some(err);
+            }
+            None => {}
+        }
+    }
+
+    fn sweep_view(&mut self, ui: &mut egui::Ui, id: usize) {
+        let names: Vec<&'static str> = match self.components.get_mut(&id) {
+            Some(component) => component
+                .component_type
+                .parameters_mut()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+            None => return,
+        };
+        if names.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.heading("Parameter Sweep");
+        let settings = &mut self.sweep_settings;
+        settings.parameter = settings.parameter.min(names.len() - 1);
+        egui::ComboBox::from_label("Parameter")
+            .selected_text(names[settings.parameter])
+            .show_ui(ui, |ui| {
+                for (i, name) in names.iter().enumerate() {
+                    ui.selectable_value(&mut settings.parameter, i, *name);
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.add(egui::DragValue::new(&mut settings.from).speed(0.1));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut settings.to).speed(0.1));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Step");
+            ui.add(
+                egui::DragValue::new(&mut settings.step)
+                    .speed(0.05)
+                    .clamp_range(0.001..=f32::MAX),
+            );
+        });
+        let runs = settings.values().len();
+        let button = egui::Button::new(format!("Run {} simulations", runs));
+        if ui
+            .add_enabled(runs > 0 && self.sweep_run.is_none(), button)
+            .on_hover_text(format!("At most {} runs", MAX_SWEEP_RUNS))
+            .clicked()
+        {
+            self.start_sweep(id);
+        }
+        if let Some(sweep_run) = &self.sweep_run {
+            ui.label(format!(
+                "Running: {} of {} runs",
+                sweep_run.runs, sweep_run.total
+            ));
+        }
+    }
+
+    // Searches for the gains of PID `pid` that minimise the value of the
+    // error-integral block `cost` at the end of a run.
+    fn start_autotune(&mut self, pid: usize, cost: usize) {
+        let gains = match self.components.get(&pid).map(|c| &c.component_type) {
+            Some(ComponentType::PIDController { kp, ki, kd, .. }) => [*kp, *ki, *kd],
+            _ => return,
+        };
+        let mut app = self.run_copy();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut best_cost = f32::INFINITY;
+            let mut failure = None;
+            let mut evaluations = 0;
+            let (best, cost) = nelder_mead(
+                |x| {
+                    if let Some(ComponentType::PIDController { kp, ki, kd, .. }) =
+                        app.components.get_mut(&pid).map(|c| &mut c.component_type)
+                    {
+                        // Negative gains are evaluated as zero.
+                        (*kp, *ki, *kd) = (x[0].max(0.0), x[1].max(0.0), x[2].max(0.0));
+                    }
+                    match app.simulate() {
+                        // Gains that destabilise the loop are just very costly.
+                        Err(SimError::Diverged { .. }) => return f32::INFINITY,
+                        Err(err) => {
+                            failure = Some(err.to_string());
+                            return f32::INFINITY;
+                        }
+                        Ok(()) => {}
+                    }
+                    let value = app
+                        .costs()
+                        .into_iter()
+                        .find(|(id, ..)| *id == cost)
+                        .map_or(f32::INFINITY, |(.., value)| value);
+                    evaluations += 1;
+                    best_cost = best_cost.min(value);
+                    let _ = sender.send(AutotuneMessage::Progress {
+                        evaluations,
+                        best_cost,
+                    });
+                    value
+                },
+                &gains,
+                300,
+            );
+            let result = match failure {
+                Some(err) => Err(err),
+                None => Ok(([best[0].max(0.0), best[1].max(0.0), best[2].max(0.0)], cost)),
+            };
+            let _ = sender.send(AutotuneMessage::Done(result));
+        });
+        self.autotune = Some(Autotune {
+            pid,
+            receiver,
+            evaluations: 0,
+            best_cost: f32::INFINITY,
+            finished: false,
+        });
+    }
+
+    fn poll_autotune(&mut self) {
+        let autotune = match &mut self.autotune {
+            Some(autotune) if !autotune.finished => autotune,
+            _ => return,
+        };
+        let mut result = None;
+        for message in autotune.receiver.try_iter() {
+            match message {
+                AutotuneMessage::Progress {
+                    evaluations,
+                    best_cost,
+                } => {
+                    autotune.evaluations = evaluations;
+                    autotune.best_cost = best_cost;
+                }
+                AutotuneMessage::Done(done) => result = Some(done),
+            }
+        }
+        let pid = autotune.pid;
+        match result {
+            Some(Ok((gains, cost))) => {
+                autotune.finished = true;
+                autotune.best_cost = cost;
+                if let Some(ComponentType::PIDController { kp, ki, kd, .. }) =
+                    self.components.get_mut(&pid).map(|c| &mut c.component_type)
+                {
+                    (*kp, *ki, *kd) = (gains[0], gains[1], gains[2]);
+                }
+            }
+            Some(Err(err)) => {
+                self.autotune = None;
+                self.last_error = Some(format!("Autotune failed: {}", err));
+            }
+            None => {}
+        }
+    }
+
+    fn autotune_view(&mut self, ui: &mut egui::Ui, pid: usize) {
+        ui.heading("Autotune");
+        let mut costs: Vec<usize> = self
+            .components
+            .values()
+            .filter(|c| matches!(c.component_type, ComponentType::ErrorIntegral { .. }))
+            .map(|c| c.id)
+            .collect();
+        if costs.is_empty() {
+            ui.label("Add an Error Integral block on the loop error to autotune");
+            return;
+        }
+        costs.sort();
+        let cost = match self.autotune_cost {
+            Some(id) if costs.contains(&id) => id,
+            _ => costs[0],
+        };
+        self.autotune_cost = Some(cost);
+        egui::ComboBox::from_label("Minimise")
+            .selected_text(self.components[&cost].label())
+            .show_ui(ui, |ui| {
+                for id in costs {
+                    let label = self.components[&id].label();
+                    ui.selectable_value(&mut self.autotune_cost, Some(id), label);
+                }
+            });
+
+        let running = self
+            .autotune
+            .as_ref()
+            .is_some_and(|autotune| !autotune.finished);
+        if ui
+            .add_enabled(!running, egui::Button::new("Autotune"))
+            .clicked()
+        {
+            self.start_autotune(pid, cost);
+        }
+        if let Some(autotune) = self.autotune.as_ref().filter(|a| a.pid == pid) {
+            let state = if autotune.finished { "Done" } else { "Running" };
+            ui.label(format!(
+                "{}: {} runs, best cost {:.5}",
+                state, autotune.evaluations, autotune.best_cost
+            ));
+        }
+    }
+
+    // Poles (×) and zeros (○) of `num / den`, against the imaginary axis for
+    // a continuous block or the unit circle for a discrete one.
+    fn pole_zero_view(&self, ui: &mut egui::Ui, num: &[f32], den: &[f32], discrete: bool) {
+        let to_points = |roots: &[Complex<f64>]| -> Vec<[f64; 2]> {
+            roots.iter().map(|root| [root.re, root.im]).collect()
+        };
+        let poles = poly_roots(den);
+        let zeros = poly_roots(num);
+        let stable = poles.iter().all(|pole| {
+            if discrete {
+                pole.norm() < 1.0
+            } else {
+                pole.re < 0.0
+            }
+        });
+        ui.heading("Poles and Zeros");
+        if stable {
+            ui.label("Stable");
+        } else {
+            ui.colored_label(egui::Color32::RED, "Unstable");
+        }
+        Plot::new("pole_zero")
+            .height(200.0)
+            .data_aspect(1.0)
+            .legend(Legend::default())
+            .include_x(-1.0)
+            .include_x(1.0)
+            .include_y(-1.0)
+            .include_y(1.0)
+            .show(ui, |plot_ui| {
+                let reference = egui::Color32::from_white_alpha(80);
+                if discrete {
+                    let circle: PlotPoints = (0..=100)
+                        .map(|i| {
+                            let angle = i as f64 / 100.0 * std::f64::consts::TAU;
+                            [angle.cos(), angle.sin()]
+                        })
+                        .collect();
+                    plot_ui.line(Line::new(circle).color(reference));
+                } else {
+                    plot_ui.vline(VLine::new(0.0).color(reference));
+                }
+                plot_ui.points(
+                    Points::new(to_points(&poles))
+                        .shape(MarkerShape::Cross)
+                        .radius(5.0)
+                        .name("Poles"),
+                );
+                plot_ui.points(
+                    Points::new(to_points(&zeros))
+                        .shape(MarkerShape::Circle)
+                        .filled(false)
+                        .radius(5.0)
+                        .name("Zeros"),
+                );
+            });
+    }
+
+    fn bode_view(&mut self, ui: &mut egui::Ui, num: &[f32], den: &[f32]) {
+        let (min, max) = &mut self.bode_decades;
+        ui.horizontal(|ui| {
+            ui.heading("Bode");
+            ui.label("ω from 10^");
+            ui.add(
+                egui::DragValue::new(min)
+                    .speed(0.1)
+                    .clamp_range(-6.0..=*max - 0.1),
+            );
+            ui.label("to 10^");
+            ui.add(
+                egui::DragValue::new(max)
+                    .speed(0.1)
+                    .clamp_range(*min + 0.1..=6.0),
+            );
+            ui.label("rad/s");
+        });
+
+        let samples = 200;
+        let decades: Vec<f64> = (0..samples)
+            .map(|i| *min as f64 + (*max - *min) as f64 * i as f64 / (samples - 1) as f64)
+            .collect();
+        let omegas: Vec<f64> = decades.iter().map(|d| 10f64.powf(*d)).collect();
+        let response = frequency_response(num, den, &omegas);
+        let points = |pick: fn(&(f64, f64)) -> f64| -> PlotPoints {
+            decades
+                .iter()
+                .zip(&response)
+                .map(|(d, r)| [*d, pick(r)])
+                .collect()
+        };
+        let magnitude = points(|r| r.0);
+        let phase = points(|r| r.1);
+
+        ui.columns(2, |columns| {
+            Plot::new("bode_magnitude")
+                .view_aspect(2.0)
+                .show(&mut columns[0], |plot_ui| {
+                    plot_ui.line(Line::new(magnitude).name("Magnitude (dB)"));
+                });
+            Plot::new("bode_phase")
+                .view_aspect(2.0)
+                .show(&mut columns[1], |plot_ui| {
+                    plot_ui.line(Line::new(phase).name("Phase (deg)"));
+                });
+        });
+        ui.label("x axis: log10 ω (rad/s)");
+    }
+
+    fn context_menu_view(&mut self, ctx: &egui::Context) {
+        let (id, pos) = match &self.context_menu {
+            Some(menu) if self.components.contains_key(&menu.component) => {
+                (menu.component, menu.pos)
+            }
+            _ => {
+                self.context_menu = None;
+                return;
+            }
+        };
+        let mut close = false;
+        let mut toggled = false;
+        let response = egui::Area::new("component_menu")
+            .order(egui::Order::Foreground)
+            .fixed_pos(pos)
+            .show(ctx, |ui| {
+                egui::Frame::menu(ui.style()).show(ui, |ui| {
+                    ui.set_min_width(140.0);
+                    let component = self.components.get_mut(&id).unwrap();
+                    let mut name = component.name.clone().unwrap_or_default();
+                    ui.horizontal(|ui| {
+                        ui.label("Rename");
+                        if ui.text_edit_singleline(&mut name).changed() {
+                            component.name = (!name.is_empty()).then_some(name);
+                        }
+                    });
+                    ui.separator();
+                    if ui.button("Edit Parameters").clicked() {
+                        self.selected_components = HashSet::from([id]);
+                        close = true;
+                    }
+                    let toggle = if component.enabled {
+                        "Disable (Bypass)"
+                    } else {
+                        "Enable"
+                    };
+                    if ui.button(toggle).clicked() {
+                        component.enabled = !component.enabled;
+                        toggled = true;
+                        close = true;
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        self.selected_components = HashSet::from([id]);
+                        self.duplicate_selected();
+                        close = true;
+                    }
+                    if ui.button("Bring to Front").clicked() {
+                        self.bring_to_front(id);
+                        close = true;
+                    }
+                    if ui.button("Delete").clicked() {
+                        self.remove_component(id);
+                        close = true;
+                    }
+                });
+            })
+            .response;
+        match &mut self.context_menu {
+            Some(menu) if !close => menu.rect = response.rect,
+            _ => self.context_menu = None,
+        }
+        // Bypassing a loop-breaking block changes which wires feed through.
+        if toggled && self.run.is_some() {
+            match self.evaluation_order() {
+                Ok(order) => {
+                    if let Some(run) = &mut self.run {
+                        run.order = order;
+                    }
+                }
+                Err(err) => {
+                    self.stop();
+                    self.last_error = Some(err.to_string());
+                }
+            }
+        }
+    }
+
+    fn parameter_editor(&mut self, ui: &mut egui::Ui, id: usize) {
+        let component = match self.components.get_mut(&id) {
+            Some(component) => component,
+            None => return,
+        };
+        ui.label(format!("Component {}", id));
+        let mut name = component.name.clone().unwrap_or_default();
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            if ui.text_edit_singleline(&mut name).changed() {
+                component.name = (!name.is_empty()).then_some(name);
+            }
+        });
+        ui.separator();
+
+        let mut ports_changed = false;
+        match &mut component.component_type {
+            ComponentType::Delay { steps, initial } => {
+                ui.horizontal(|ui| {
+                    ui.label("Delay steps");
+                    ui.add(egui::DragValue::new(steps).clamp_range(0..=10_000));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Initial output");
+                    ui.add(egui::DragValue::new(initial).speed(0.1));
+                });
+            }
+            ComponentType::Memory { initial } => {
+                ui.horizontal(|ui| {
+                    ui.label("Initial output");
+                    ui.add(egui::DragValue::new(initial).speed(0.1));
+                });
+            }
+            ComponentType::ZeroOrderHold { sample_every } => {
+                ui.horizontal(|ui| {
+                    ui.label("Sample every");
+                    ui.add(
+                        egui::DragValue::new(sample_every)
+                            .clamp_range(1..=10_000)
+                            .suffix(" steps"),
+                    );
+                });
+            }
+            ComponentType::PIDController {
+                kp,
+                ki,
+                kd,
+                output_limits,
+            } => {
+                for (name, value) in [("Kp", kp), ("Ki", ki), ("Kd", kd)] {
+                    ui.horizontal(|ui| {
+                        ui.label(name);
+                        ui.add(egui::DragValue::new(value).speed(0.01));
+                    });
+                }
+                let mut limited = output_limits.is_some();
+                if ui.checkbox(&mut limited, "Limit output").changed() {
+                    *output_limits = limited.then_some((-1.0, 1.0));
+                }
+                if let Some((min, max)) = output_limits {
+                    let upper = *max;
+                    ui.horizontal(|ui| {
+                        ui.label("Min");
+                        ui.add(
+                            egui::DragValue::new(min)
+                                .speed(0.1)
+                                .clamp_range(f32::MIN..=upper),
+                        );
+                    });
+                    let lower = *min;
+                    ui.horizontal(|ui| {
+                        ui.label("Max");
+                        ui.add(
+                            egui::DragValue::new(max)
+                                .speed(0.1)
+                                .clamp_range(lower..=f32::MAX),
+                        );
+                    });
+                }
+            }
+            ComponentType::Gain { k } => {
+                ui.horizontal(|ui| {
+                    ui.label("Gain");
+                    ui.add(egui::DragValue::new(k).speed(0.1));
+                });
+            }
+            ComponentType::Constant { value } => {
+                ui.horizontal(|ui| {
+                    ui.label("Value");
+                    ui.add(egui::DragValue::new(value).speed(0.1));
+                });
+            }
+            ComponentType::Sine {
+                amplitude,
+                freq_hz,
+                phase,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.label("Amplitude");
+                    ui.add(egui::DragValue::new(amplitude).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Frequency");
+                    ui.add(
+                        egui::DragValue::new(freq_hz)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" Hz"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Phase");
+                    ui.add(egui::DragValue::new(phase).speed(0.01).suffix(" rad"));
+                });
+            }
+            ComponentType::MultiSine { components } => {
+                let mut remove = None;
+                egui::Grid::new("multi_sine").show(ui, |ui| {
+                    ui.label("Amplitude");
+                    ui.label("Frequency");
+                    ui.label("Phase");
+                    ui.end_row();
+                    for (i, (amplitude, freq_hz, phase)) in components.iter_mut().enumerate() {
+                        ui.add(egui::DragValue::new(amplitude).speed(0.1));
+                        ui.add(
+                            egui::DragValue::new(freq_hz)
+                                .speed(0.01)
+                                .clamp_range(0.0..=f32::MAX)
+                                .suffix(" Hz"),
+                        );
+                        ui.add(egui::DragValue::new(phase).speed(0.01).suffix(" rad"));
+                        if ui.small_button("−").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = remove {
+                    components.remove(i);
+                }
+                if ui.small_button("+").clicked() {
+                    let freq_hz = components
+                        .last()
+                        .map_or(1.0, |(_, freq_hz, _)| freq_hz * 2.0);
+                    components.push((1.0, freq_hz, 0.0));
+                }
+            }
+            ComponentType::Pulse {
+                amplitude,
+                period,
+                duty,
+            } => {
+                ui.horizontal(|ui| {
+                    ui.label("Amplitude");
+                    ui.add(egui::DragValue::new(amplitude).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Period");
+                    ui.add(
+                        egui::DragValue::new(period)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Duty");
+                    ui.add(egui::Slider::new(duty, 0.0..=1.0));
+                });
+            }
+            ComponentType::Noise { std_dev, seed } => {
+                ui.horizontal(|ui| {
+                    ui.label("Std dev");
+                    ui.add(
+                        egui::DragValue::new(std_dev)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Seed");
+                    ui.add(egui::DragValue::new(seed));
+                });
+            }
+            ComponentType::Ramp { slope, start_time } => {
+                ui.horizontal(|ui| {
+                    ui.label("Slope");
+                    ui.add(egui::DragValue::new(slope).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Start time");
+                    ui.add(
+                        egui::DragValue::new(start_time)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" s"),
+                    );
+                });
+            }
+            ComponentType::Saturation { min, max } => {
+                let upper = *max;
+                ui.horizontal(|ui| {
+                    ui.label("Min");
+                    ui.add(
+                        egui::DragValue::new(min)
+                            .speed(0.1)
+                            .clamp_range(f32::MIN..=upper),
+                    );
+                });
+                let lower = *min;
+                ui.horizontal(|ui| {
+                    ui.label("Max");
+                    ui.add(
+                        egui::DragValue::new(max)
+                            .speed(0.1)
+                            .clamp_range(lower..=f32::MAX),
+                    );
+                });
+            }
+            ComponentType::LookupTable {
+                breakpoints,
+                values,
+            } => {
+                values.resize(breakpoints.len(), 0.0);
+                let mut remove = None;
+                egui::Grid::new("lookup_table").show(ui, |ui| {
+                    ui.label("Input");
+                    ui.label("Output");
+                    ui.end_row();
+                    for (i, (x, y)) in breakpoints.iter_mut().zip(values.iter_mut()).enumerate() {
+                        ui.add(egui::DragValue::new(x).speed(0.1));
+                        ui.add(egui::DragValue::new(y).speed(0.1));
+                        if ui.small_button("−").clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+                if let Some(i) = remove {
+                    breakpoints.remove(i);
+                    values.remove(i);
+                }
+                if ui.small_button("+").clicked() {
+                    let x = breakpoints.last().map_or(0.0, |x| x + 1.0);
+                    let y = values.last().copied().unwrap_or(0.0);
+                    breakpoints.push(x);
+                    values.push(y);
+                }
+                if breakpoints.windows(2).any(|pair| pair[0] > pair[1]) {
+                    ui.colored_label(egui::Color32::RED, "Inputs must be ascending");
+                }
+            }
+            ComponentType::StateSpace { a, b, c, d } => {
+                let mut order = a.len();
+                ui.horizontal(|ui| {
+                    ui.label("States");
+                    ui.add(egui::DragValue::new(&mut order).clamp_range(1..=8));
+                });
+                if order != a.len() {
+                    a.resize(order, Vec::new());
+                    for row in a.iter_mut() {
+                        row.resize(order, 0.0);
+                    }
+                    b.resize(order, 0.0);
+                    c.resize(order, 0.0);
+                }
+                ui.label("A and B");
+                egui::Grid::new("state_space_ab").show(ui, |ui| {
+                    for (row, bi) in a.iter_mut().zip(b.iter_mut()) {
+                        for aij in row.iter_mut() {
+                            ui.add(egui::DragValue::new(aij).speed(0.1));
+                        }
+                        ui.label("│");
+                        ui.add(egui::DragValue::new(bi).speed(0.1));
+                        ui.end_row();
+                    }
+                });
+                ui.label("C and D");
+                egui::Grid::new("state_space_cd").show(ui, |ui| {
+                    for ci in c.iter_mut() {
+                        ui.add(egui::DragValue::new(ci).speed(0.1));
+                    }
+                    ui.label("│");
+                    ui.add(egui::DragValue::new(d).speed(0.1));
+                    ui.end_row();
+                });
+            }
+            ComponentType::Relay {
+                on_threshold,
+                off_threshold,
+                on_value,
+                off_value,
+            } => {
+                let upper = *on_threshold;
+                ui.horizontal(|ui| {
+                    ui.label("Switch off below");
+                    ui.add(
+                        egui::DragValue::new(off_threshold)
+                            .speed(0.1)
+                            .clamp_range(f32::MIN..=upper),
+                    );
+                });
+                let lower = *off_threshold;
+                ui.horizontal(|ui| {
+                    ui.label("Switch on above");
+                    ui.add(
+                        egui::DragValue::new(on_threshold)
+                            .speed(0.1)
+                            .clamp_range(lower..=f32::MAX),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("On value");
+                    ui.add(egui::DragValue::new(on_value).speed(0.1));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Off value");
+                    ui.add(egui::DragValue::new(off_value).speed(0.1));
+                });
+            }
+            ComponentType::IntegratorIC { initial, reset } => {
+                ui.horizontal(|ui| {
+                    ui.label("Initial value");
+                    ui.add(egui::DragValue::new(initial).speed(0.1));
+                });
+                let mut resettable = reset.is_some();
+                if ui.checkbox(&mut resettable, "Reset input").changed() {
+                    *reset = resettable.then_some(0.5);
+                    // Drops the wire on the reset port when it goes away.
+                    ports_changed = true;
+                }
+                if let Some(threshold) = reset {
+                    ui.horizontal(|ui| {
+                        ui.label("Reset threshold");
+                        ui.add(egui::DragValue::new(threshold).speed(0.1));
+                    });
+                    ui.label("Returns to zero when reset rises through the threshold");
+                }
+            }
+            ComponentType::TransportDelay { seconds } => {
+                ui.horizontal(|ui| {
+                    ui.label("Delay");
+                    ui.add(
+                        egui::DragValue::new(seconds)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" s"),
+                    );
+                });
+            }
+            ComponentType::Inport { index } | ComponentType::Outport { index } => {
+                ui.horizontal(|ui| {
+                    ui.label("Port");
+                    let mut number = *index + 1;
+                    ui.add(
+                        egui::DragValue::new(&mut number).clamp_range(1..=SUBSYSTEM_INPUTS.len()),
+                    );
+                    *index = number - 1;
+                });
+            }
+            ComponentType::Mux { width } | ComponentType::Demux { width } => {
+                ui.horizontal(|ui| {
+                    ui.label("Width");
+                    ports_changed = ui
+                        .add(egui::DragValue::new(width).clamp_range(2..=SUBSYSTEM_INPUTS.len()))
+                        .changed();
+                });
+            }
+            ComponentType::Subsystem { model } => {
+                let (inputs, outputs) = model.boundary_ports();
+                ui.label(format!(
+                    "{} blocks, {} inputs, {} outputs",
+                    model.components.len(),
+                    inputs,
+                    outputs
+                ));
+                ui.label("Double-click the block to edit its contents");
+            }
+            ComponentType::ErrorIntegral { mode } => {
+                ui.horizontal(|ui| {
+                    ui.label("Measure");
+                    for cost in [ErrorCost::Iae, ErrorCost::Ise, ErrorCost::Itae] {
+                        ui.selectable_value(mode, cost, cost.name());
+                    }
+                });
+            }
+            ComponentType::MinMax { mode } => {
+                ui.horizontal(|ui| {
+                    ui.label("Output");
+                    ui.selectable_value(mode, MinOrMax::Min, "Min");
+                    ui.selectable_value(mode, MinOrMax::Max, "Max");
+                });
+            }
+            ComponentType::Derivative { filtered, n } => {
+                ui.horizontal(|ui| {
+                    ui.label("Mode");
+                    ui.selectable_value(filtered, false, "Raw");
+                    ui.selectable_value(filtered, true, "Filtered");
+                });
+                ui.add_enabled_ui(*filtered, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Filter N");
+                        ui.add(
+                            egui::DragValue::new(n)
+                                .speed(0.5)
+                                .clamp_range(0.001..=f32::MAX)
+                                .suffix(" rad/s"),
+                        );
+                    });
+                });
+            }
+            ComponentType::NumericDisplay { decimals } => {
+                ui.horizontal(|ui| {
+                    ui.label("Decimal places");
+                    ui.add(egui::DragValue::new(decimals).clamp_range(0..=9));
+                });
+            }
+            ComponentType::Quantizer { step } => {
+                ui.horizontal(|ui| {
+                    ui.label("Step");
+                    ui.add(
+                        egui::DragValue::new(step)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX),
+                    );
+                });
+            }
+            ComponentType::Switch { threshold } => {
+                ui.horizontal(|ui| {
+                    ui.label("Threshold");
+                    ui.add(egui::DragValue::new(threshold).speed(0.1));
+                });
+                ui.label("Passes in1 while control ≥ threshold, otherwise in2");
+            }
+            ComponentType::RateLimiter { rising, falling } => {
+                ui.horizontal(|ui| {
+                    ui.label("Rising rate");
+                    ui.add(
+                        egui::DragValue::new(rising)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" /s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Falling rate");
+                    ui.add(
+                        egui::DragValue::new(falling)
+                            .speed(0.1)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" /s"),
+                    );
+                });
+            }
+            ComponentType::LowPass { tau } => {
+                ui.horizontal(|ui| {
+                    ui.label("Time constant");
+                    ui.add(
+                        egui::DragValue::new(tau)
+                            .speed(0.01)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" s"),
+                    );
+                });
+            }
+            ComponentType::TransferFunctionCoeffs { num, den } => {
+                ui.label("Coefficients in descending powers of s");
+                coefficient_editor(ui, "Numerator", num);
+                coefficient_editor(ui, "Denominator", den);
+            }
+            ComponentType::DiscreteTransferFunction { num, den } => {
+                ui.label("Coefficients in descending powers of z");
+                coefficient_editor(ui, "Numerator", num);
+                coefficient_editor(ui, "Denominator", den);
+                if num.len() > den.len() || den.first() == Some(&0.0) {
+                    ui.colored_label(egui::Color32::RED, "Not causal: output is held at zero");
+                }
+            }
+            ComponentType::Sum { signs } => {
+                ui.label("Input signs");
+                ui.horizontal(|ui| {
+                    for (i, sign) in signs.iter_mut().enumerate() {
+                        let text = if *sign < 0.0 { "−" } else { "+" };
+                        if ui.button(format!("{}: {}", i + 1, text)).clicked() {
+                            *sign = -*sign;
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let full = signs.len() >= SUBSYSTEM_INPUTS.len();
+                    if !full && ui.small_button("Add input").clicked() {
+                        signs.push(1.0);
+                        ports_changed = true;
+                    }
+                    if signs.len() > 1 && ui.small_button("Remove input").clicked() {
+                        signs.pop();
+                        ports_changed = true;
+                    }
+                });
+            }
+            _ => {
+                ui.label("No editable parameters");
+            }
+        }
+        if ports_changed {
+            self.prune_port_edges(id);
+        }
+    }
+}
+
+fn draw_grid(painter: &egui::Painter, camera: Camera, grid_size: f32) {
+    if grid_size * camera.zoom < 4.0 {
+        return;
+    }
+    let clip = painter.clip_rect();
+    let min = camera.to_world(clip.min);
+    let max = camera.to_world(clip.max);
+    let stroke = (1.0, egui::Color32::from_white_alpha(12));
+
+    let mut x = (min.x / grid_size).floor() * grid_size;
+    while x <= max.x {
+        let screen_x = camera.to_screen(egui::pos2(x, 0.0)).x;
+        painter.line_segment(
+            [
+                egui::pos2(screen_x, clip.top()),
+                egui::pos2(screen_x, clip.bottom()),
+            ],
+            stroke,
+        );
+        x += grid_size;
+    }
+    let mut y = (min.y / grid_size).floor() * grid_size;
+    while y <= max.y {
+        let screen_y = camera.to_screen(egui::pos2(0.0, y)).y;
+        painter.line_segment(
+            [
+                egui::pos2(clip.left(), screen_y),
+                egui::pos2(clip.right(), screen_y),
+            ],
+            stroke,
+        );
+        y += grid_size;
+    }
+}
+
+// Grey for zero, shading to red for positive and blue for negative values
+// as they approach `scale` in magnitude.
+fn signal_color(value: f32, scale: f32) -> egui::Color32 {
+    let t = if scale > 0.0 {
+        (value.abs() / scale).min(1.0)
+    } else {
+        0.0
+    };
+    let (r, g, b) = if value < 0.0 {
+        (60.0, 120.0, 255.0)
+    } else {
+        (255.0, 80.0, 60.0)
+    };
+    let mix = |from: f32, to: f32| (from + t * (to - from)) as u8;
+    egui::Color32::from_rgb(mix(211.0, r), mix(211.0, g), mix(211.0, b))
+}
+
+// Legend for `signal_color` in the bottom-right corner of the canvas.
+fn draw_colorbar(painter: &egui::Painter, canvas: egui::Rect, scale: f32) {
+    let bar = egui::Rect::from_min_size(
+        canvas.right_bottom() - egui::vec2(170.0, 36.0),
+        egui::vec2(150.0, 10.0),
+    );
+    let segments = 30;
+    let width = bar.width() / segments as f32;
+    for i in 0..segments {
+        let value = scale * (2.0 * (i as f32 + 0.5) / segments as f32 - 1.0);
+        let min = bar.left_top() + egui::vec2(i as f32 * width, 0.0);
+        painter.rect_filled(
+            egui::Rect::from_min_size(min, egui::vec2(width, bar.height())),
+            0.0,
+            signal_color(value, scale),
+        );
+    }
+    let font = FontId::proportional(11.0);
+    let (low, high) = (format!("{:.3}", -scale), format!("{:.3}", scale));
+    for (align, x, text) in [
+        (egui::Align2::LEFT_TOP, bar.left(), low),
+        (egui::Align2::CENTER_TOP, bar.center().x, "0".to_string()),
+        (egui::Align2::RIGHT_TOP, bar.right(), high),
+    ] {
+        painter.text(
+            egui::pos2(x, bar.bottom() + 2.0),
+            align,
+            text,
+            font.clone(),
+            egui::Color32::LIGHT_GRAY,
+        );
+    }
+}
+
+// Filled arrowhead whose tip stops at the edge of the input port's circle.
+fn draw_arrowhead(painter: &egui::Painter, from: Pos2, to: Pos2, zoom: f32, color: egui::Color32) {
+    if let Some(points) = arrowhead(from, to, zoom) {
+        painter.add(egui::Shape::convex_polygon(
+            points.to_vec(),
+            color,
+            egui::Stroke::none(),
+        ));
+    }
+}
+
+fn arrowhead(from: Pos2, to: Pos2, zoom: f32) -> Option<[Pos2; 3]> {
+    let direction = (to - from).normalized();
+    if !direction.is_finite() {
+        return None;
+    }
+    let tip = to - direction * PORT_RADIUS * zoom;
+    let back = tip - direction * 8.0 * zoom;
+    let side = direction.rot90() * 4.0 * zoom;
+    Some([tip, back + side, back - side])
+}
+
+fn svg_color(color: egui::Color32) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b())
+}
+
+fn svg_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Quotes a CSV field as RFC 4180 asks when it holds a separator, quote or
+// line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn coefficient_editor(ui: &mut egui::Ui, name: &str, coeffs: &mut Vec<f32>) {
+    ui.horizontal_wrapped(|ui| {
+        ui.label(name);
+        for coeff in coeffs.iter_mut() {
+            ui.add(egui::DragValue::new(coeff).speed(0.01));
+        }
+        if ui.small_button("+").clicked() {
+            coeffs.push(0.0);
+        }
+        if coeffs.len() > 1 && ui.small_button("−").clicked() {
+            coeffs.pop();
+        }
+    });
+}
+
+impl App for SimulatorApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut Frame) {
+        let title = if self.metadata.title.is_empty() {
+            " Simulator".to_string()
+        } else {
+            format!("{} — Simulator", self.metadata.title)
+        };
+        if title != self.window_title {
+            frame.set_window_title(&title);
+            self.window_title = title;
+        }
+
+        let mut block_shortcut = None;
+        if !ctx.wants_keyboard_input() {
+            let input = ctx.input();
+            let command = input.modifiers.command;
+            let undo = command && !input.modifiers.shift && input.key_pressed(egui::Key::Z);
+            let redo = command
+                && (input.key_pressed(egui::Key::Y)
+                    || (input.modifiers.shift && input.key_pressed(egui::Key::Z)));
+            let copy = command && input.key_pressed(egui::Key::C);
+            let paste = command && input.key_pressed(egui::Key::V);
+            let duplicate = command && input.key_pressed(egui::Key::D);
+            // Arrow keys nudge the selection by a pixel, or a grid step with Shift.
+            let nudge_step = if input.modifiers.shift {
+                self.grid_size
+            } else {
+                1.0
+            };
+            let nudge = [
+                (egui::Key::ArrowLeft, egui::vec2(-1.0, 0.0)),
+                (egui::Key::ArrowRight, egui::vec2(1.0, 0.0)),
+                (egui::Key::ArrowUp, egui::vec2(0.0, -1.0)),
+                (egui::Key::ArrowDown, egui::vec2(0.0, 1.0)),
+            ]
+            .into_iter()
+            .filter(|(key, _)| input.key_pressed(*key))
+            .fold(egui::Vec2::ZERO, |offset, (_, step)| offset + step)
+                * nudge_step;
+            let toggle_shortcuts = input
+                .events
+                .iter()
+                .any(|event| matches!(event, egui::Event::Text(text) if text == "?"));
+            if input.modifiers.is_none() {
+                block_shortcut = block_shortcuts()
+                    .into_iter()
+                    .find(|(key, ..)| input.key_pressed(*key))
+                    .map(|(_, _, component_type)| component_type);
+            }
+            drop(input);
+            if toggle_shortcuts {
+                self.show_shortcuts = !self.show_shortcuts;
+            }
+            if undo {
+                self.undo();
+            } else if redo {
+                self.redo();
+            }
+            if copy {
+                self.copy_selected();
+            } else if paste {
+                self.paste();
+            } else if duplicate {
+                self.duplicate_selected();
+            }
+            if nudge != egui::Vec2::ZERO {
+                self.nudge_selected(nudge);
+            }
+        }
+        if self.running_to_end {
+            self.run_for(RUN_FRAME_BUDGET);
+            ctx.request_repaint();
+        } else if self.playing {
+            self.step_live(self.steps_per_frame);
+            ctx.request_repaint();
+        }
+        if self.autotune.as_ref().is_some_and(|a| !a.finished) {
+            self.poll_autotune();
+            ctx.request_repaint();
+        }
+        if self.sweep_run.is_some() {
+            self.poll_sweep();
+            ctx.request_repaint();
+        }
+    
+        egui::Window::new("Model info")
+            .open(&mut self.show_metadata)
+            .show(ctx, |ui| {
+                let metadata = &mut self.metadata;
+                egui::Grid::new("metadata").num_columns(2).show(ui, |ui| {
+                    ui.label("Title");
+                    ui.text_edit_singleline(&mut metadata.title);
+                    ui.end_row();
+                    ui.label("Author");
+                    ui.text_edit_singleline(&mut metadata.author);
+                    ui.end_row();
+                    ui.label("Created");
+                    ui.label(match metadata.created {
+                        0 => "unknown".to_string(),
+                        created => format_date(created),
+                    });
+                    ui.end_row();
+                });
+                ui.label("Notes");
+                ui.text_edit_multiline(&mut metadata.notes);
+            });
+
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut self.show_shortcuts)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts").show(ui, |ui| {
+                    for (key, name, _) in block_shortcuts() {
+                        ui.monospace(format!("{:?}", key).to_lowercase());
+                        ui.label(format!("Add {}", name));
+                        ui.end_row();
+                    }
+                    for (keys, action) in [
+                        ("Ctrl+C / Ctrl+V", "Copy / paste"),
+                        ("Ctrl+D", "Duplicate"),
+                        ("Ctrl+Z / Ctrl+Y", "Undo / redo"),
+                        ("Delete", "Remove selection"),
+                        ("Arrows / Shift+Arrows", "Nudge by 1px / one grid step"),
+                        ("Right-click", "Block menu"),
+                        ("Esc", "Cancel wire"),
+                        ("?", "Toggle this list"),
+                    ] {
+                        ui.monospace(keys);
+                        ui.label(action);
+                        ui.end_row();
+                    }
+                });
+            });
+
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("dt");
+                ui.add(
+                    egui::DragValue::new(&mut self.dt)
+                        .speed(0.001)
+                        .clamp_range(0.0001..=10.0),
+                );
+                ui.label("Duration");
+                ui.add(
+                    egui::DragValue::new(&mut self.total_time)
+                        .speed(0.1)
+                        .clamp_range(0.0..=100_000.0)
+                        .suffix(" s"),
+                );
+                let mut bounded = self.divergence_bound.is_some();
+                ui.checkbox(&mut bounded, "Diverge at ±").on_hover_text(
+                    "Also stop the run when any output grows past this; \
+                     non-finite outputs always stop it",
+                );
+                if bounded != self.divergence_bound.is_some() {
+                    self.divergence_bound = bounded.then_some(1e6);
+                }
+                if let Some(bound) = &mut self.divergence_bound {
+                    ui.add(
+                        egui::DragValue::new(bound)
+                            .speed(1000.0)
+                            .clamp_range(1.0..=f32::MAX),
+                    );
+                }
+                ui.separator();
+                ui.checkbox(&mut self.snap_to_grid, "Snap to grid");
+                if self.snap_to_grid {
+                    ui.add(
+                        egui::DragValue::new(&mut self.grid_size)
+                            .speed(1.0)
+                            .clamp_range(2.0..=200.0)
+                            .suffix(" px"),
+                    );
+                }
+                if ui.button("Fit").clicked() {
+                    self.fit_pending = true;
+                }
+                if ui.button("Auto Layout").clicked() {
+                    self.auto_layout();
+                }
+                let search = ui.add(
+                    egui::TextEdit::singleline(&mut self.search)
+                        .hint_text("Find id, name or type")
+                        .desired_width(140.0),
+                );
+                // Typing jumps to the first match; Enter steps to the next one.
+                let found = if search.changed() {
+                    self.find_component(&self.search, None)
+                } else if search.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                    search.request_focus();
+                    self.find_component(&self.search, self.selected_component())
+                } else {
+                    None
+                };
+                if let Some(id) = found {
+                    self.selected_components = HashSet::from([id]);
+                    self.selected_connection = None;
+                    self.focus_pending = Some(id);
+                }
+                ui.separator();
+                ui.checkbox(&mut self.probe_mode, "Probe wires")
+                    .on_hover_text("Click a wire to record its value without a Scope");
+                if ui
+                    .add_enabled(!self.probes.is_empty(), egui::Button::new("Clear probes"))
+                    .clicked()
+                {
+                    self.probes.clear();
+                    self.probe_data.clear();
+                }
+                ui.separator();
+                if ui
+                    .add_enabled(!self.undo_stack.is_empty(), egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(!self.redo_stack.is_empty(), egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    self.redo();
+                }
+                ui.separator();
+                egui::ComboBox::from_id_source("solver")
+                    .selected_text(format!("{:?}", self.solver))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.solver, SolverMethod::Euler, "Euler");
+                        ui.selectable_value(&mut self.solver, SolverMethod::Rk4, "Rk4");
+                    });
+                if ui.button("Run Simulation").clicked() {
+                    self.run_validated();
+                }
+                let play_label = if self.playing {
+                    "⏸ Pause"
+                } else {
+                    "▶ Play"
+                };
+                if ui.button(play_label).clicked() {
+                    if self.run.as_ref().is_some_and(Run::finished) {
+                        self.run = None;
+                    }
+                    self.running_to_end = false;
+                    self.playing = !self.playing;
+                }
+                if ui.button("Step").clicked() {
+                    self.playing = false;
+                    self.running_to_end = false;
+                    self.step_live(1);
+                }
+                if ui.button("⏹ Stop").clicked() {
+                    self.stop();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut self.steps_per_frame)
+                        .clamp_range(1..=1000)
+                        .suffix(" steps/frame"),
+                );
+                if ui.button("Reset").clicked() {
+                    self.stop();
+                    self.reset_state();
+                }
+                ui.separator();
+                if ui.button("Save").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Model", &["json"])
+                        .add_filter("Binary model", &["bin"])
+                        .save_file()
+                    {
+                        self.last_error = self
+                            .save_to_path(&path)
+                            .err()
+                            .map(|err| format!("Failed to save {}: {}", path.display(), err));
+                    }
+                }
+                if ui.button("Open").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Model", &["json", "bin"])
+                        .pick_file()
+                    {
+                        self.last_error = self
+                            .load_from_path(&path)
+                            .err()
+                            .map(|err| format!("Failed to open {}: {}", path.display(), err));
+                    }
+                }
+                if ui.button("Model Info").clicked() {
+                    self.show_metadata = !self.show_metadata;
+                }
+                if ui
+                    .button("Derivative Demo")
+                    .on_hover_text("Replace the model with a raw vs filtered derivative comparison")
+                    .clicked()
+                {
+                    self.open_model(derivative_demo());
+                }
+                if ui.button("Export Image").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("SVG", &["svg"])
+                        .save_file()
+                    {
+                        self.last_error = self
+                            .export_svg(&path)
+                            .err()
+                            .map(|err| format!("Failed to export {}: {}", path.display(), err));
+                    }
+                }
+                let focused = self.focused_scope();
+                if ui
+                    .add_enabled(focused.is_some(), egui::Button::new("Copy Data"))
+                    .on_hover_text("Copy the selected scope as tab-separated text")
+                    .clicked()
+                {
+                    if let Some(tsv) = focused.and_then(|id| self.scope_tsv(id)) {
+                        ui.output().copied_text = tsv;
+                    }
+                }
+                if ui.button("Export CSV").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("CSV", &["csv"])
+                        .save_file()
+                    {
+                        self.last_error = self
+                            .export_csv(&path)
+                            .err()
+                            .map(|err| format!("Failed to export {}: {}", path.display(), err));
+                    }
+                }
+            });
+            if !self.edit_stack.is_empty() {
+                ui.horizontal(|ui| {
+                    if ui.button("⬆ Up").clicked() {
+                        self.leave_subsystem();
+                        return;
+                    }
+                    let path: Vec<String> = self
+                        .edit_stack
+                        .iter()
+                        .map(|parent| format!("Subsystem {}", parent.subsystem))
+                        .collect();
+                    ui.label(format!("Editing: Top › {}", path.join(" › ")));
+                });
+            }
+        });
+
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                match (&self.last_error, &self.run) {
+                    (Some(error), _) => {
+                        ui.colored_label(egui::Color32::RED, error);
+                    }
+                    (None, Some(run)) if self.running_to_end => {
+                        let progress = run.step as f32 / run.steps.max(1) as f32;
+                        let text = format!("Simulating step {} of {}", run.step, run.steps);
+                        if ui.button("Cancel").clicked() {
+                            self.stop();
+                        } else {
+                            ui.add(
+                                egui::ProgressBar::new(progress)
+                                    .desired_width(240.0)
+                                    .text(text),
+                            );
+                        }
+                    }
+                    (None, Some(run)) => {
+                        let state = if run.finished() {
+                            "Finished"
+                        } else if self.playing {
+                            "Running"
+                        } else {
+                            "Paused"
+                        };
+                        ui.label(format!(
+                            "{} at t = {:.2} s (step {} of {})",
+                            state,
+                            run.step as f32 * self.dt,
+                            run.step,
+                            run.steps
+                        ));
+                    }
+                    (None, None) => {
+                        ui.label(format!("{} blocks", self.components.len()));
+                    }
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.checkbox(&mut self.logging, "Log to stdout");
+                });
+            });
+        });
+
+        let mut palette_pick = None;
+        egui::SidePanel::left("palette").show(ctx, |ui| {
+            ui.heading("Blocks");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (category, blocks) in block_palette() {
+                    egui::CollapsingHeader::new(category)
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for (name, component_type) in blocks {
+                                let button = egui::Button::new(name)
+                                    .sense(egui::Sense::click_and_drag());
+                                let response = ui
+                                    .add(button)
+                                    .on_hover_text("Click to add, or drag onto the canvas");
+                                if response.clicked() {
+                                    palette_pick = Some(component_type);
+                                } else if response.drag_started() {
+                                    self.palette_drag = Some(component_type);
+                                }
+                            }
+                        });
+                }
+            });
+        });
+
+        egui::SidePanel::left("side_panel").show(ctx, |ui| {
+            ui.heading("Simulation Output");
+
+        
+            let series: Vec<(String, Vec<[f64; 2]>)> = self
+                .scope_traces()
+                .into_iter()
+                .map(|(name, data)| {
+                    let points = data
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &value)| [self.sample_time(i) as f64, value as f64])
+                        .collect();
+                    (name, points)
+                })
+                .chain(self.probe_traces())
+                .collect();
+
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!series.is_empty(), egui::Button::new("Pin current result"))
+                    .clicked()
+                {
+                    self.pinned = Some(series.clone());
+                }
+                if ui
+                    .add_enabled(self.pinned.is_some(), egui::Button::new("Clear pin"))
+                    .clicked()
+                {
+                    self.pinned = None;
+                }
+                if ui
+                    .add_enabled(!self.sweep.is_empty(), egui::Button::new("Clear sweep"))
+                    .clicked()
+                {
+                    self.sweep.clear();
+                }
+                if ui
+                    .button("Clear Plot")
+                    .on_hover_text("Empty the scope buffers, keeping the diagram")
+                    .clicked()
+                {
+                    self.clear_plot();
+                }
+            });
+
+            ui.small("Amplitude");
+            let plot_y = self.plot_y;
+            let clip_y = |y: f64| match plot_y {
+                PlotYRange::Auto => y,
+                PlotYRange::Fixed { min, max } => y.max(min as f64).min(max as f64),
+            };
+            let clipped = |points: &[[f64; 2]]| -> Vec<[f64; 2]> {
+                points.iter().map(|[x, y]| [*x, clip_y(*y)]).collect()
+            };
+            let mut plot = Plot::new("Scope Plot")
+                .view_aspect(2.0) 
+                .legend(Legend::default())
+                .show_x(false)
+                .show_y(false);
+            if let PlotYRange::Fixed { min, max } = plot_y {
+                plot = plot.include_y(min).include_y(max);
+            }
+            let bounds = plot
+                .show(ui, |plot_ui| {
+                    let bounds = plot_ui.plot_bounds();
+                    for (i, (name, points)) in self.pinned.iter().flatten().enumerate() {
+                        let color = TRACE_COLORS[i % TRACE_COLORS.len()];
+                        plot_ui.line(
+                            Line::new(PlotPoints::new(clipped(points)))
+                                .name(format!("{} (pinned)", name))
+                                .color(color.linear_multiply(0.6))
+                                .style(LineStyle::dashed_loose()),
+                        );
+                    }
+                    for (i, (name, points)) in self.sweep.iter().enumerate() {
+                        plot_ui.line(
+                            Line::new(PlotPoints::new(clipped(points)))
+                                .name(name)
+                                .color(TRACE_COLORS[i % TRACE_COLORS.len()].linear_multiply(0.8)),
+                        );
+                    }
+                    for (i, (name, points)) in series.iter().enumerate() {
+                        plot_ui.line(
+                            Line::new(PlotPoints::new(clipped(points)))
+                                .name(name)
+                                .color(TRACE_COLORS[i % TRACE_COLORS.len()]),
+                        );
+                    }
+
+                    // Nearest sample on screen across all traces, reporting
+                    // its true value even where the trace is clipped.
+                    let pointer = match plot_ui.pointer_coordinate() {
+                        Some(pointer) => plot_ui.screen_from_plot(pointer),
+                        None => return bounds,
+                    };
+                    let nearest = series
+                        .iter()
+                        .flat_map(|(name, points)| points.iter().map(move |p| (name, *p)))
+                        .map(|(name, [x, y])| {
+                            let shown = clip_y(y);
+                            let screen = plot_ui.screen_from_plot(PlotPoint::new(x, shown));
+                            (screen.distance(pointer), name, x, y, shown)
+                        })
+                        .min_by(|a, b| a.0.total_cmp(&b.0));
+                    if let Some((_, name, x, y, shown)) = nearest {
+                        let color = egui::Color32::from_white_alpha(120);
+                        plot_ui.vline(VLine::new(x).color(color));
+                        plot_ui.hline(HLine::new(shown).color(color));
+                        plot_ui.points(Points::new(vec![[x, shown]]).radius(4.0).color(color));
+                        plot_ui.text(
+                            Text::new(
+                                PlotPoint::new(x, shown),
+                                format!("{}\nt = {:.3} s\ny = {:.4}", name, x, y),
+                            )
+                            .anchor(egui::Align2::LEFT_BOTTOM),
+                        );
+                    }
+                    bounds
+                })
+                .inner;
+            ui.vertical_centered(|ui| ui.small("Time (s)"));
+            let visible = PlotYRange::Fixed {
+                min: bounds.min()[1] as f32,
+                max: bounds.max()[1] as f32,
+            };
+            ui.horizontal(|ui| {
+                let mut autoscale = self.plot_y == PlotYRange::Auto;
+                if ui.checkbox(&mut autoscale, "Autoscale Y").changed() {
+                    self.plot_y = if autoscale { PlotYRange::Auto } else { visible };
+                }
+                if let PlotYRange::Fixed { min, max } = &mut self.plot_y {
+                    let upper = *max;
+                    ui.label("Min");
+                    ui.add(
+                        egui::DragValue::new(min)
+                            .speed(0.1)
+                            .clamp_range(f32::MIN..=upper),
+                    );
+                    let lower = *min;
+                    ui.label("Max");
+                    ui.add(
+                        egui::DragValue::new(max)
+                            .speed(0.1)
+                            .clamp_range(lower..=f32::MAX),
+                    );
+                }
+                if ui
+                    .button("Lock current view")
+                    .on_hover_text("Hold the Y range at what is shown now")
+                    .clicked()
+                {
+                    self.plot_y = visible;
+                }
+            });
+
+            self.step_response_view(ui);
+            self.spectrum_view(ui);
+            let costs = self.costs();
+            if !costs.is_empty() {
+                ui.separator();
+                ui.heading("Cost");
+                egui::Grid::new("costs").show(ui, |ui| {
+                    for (id, mode, value) in costs {
+                        ui.label(self.components[&id].label());
+                        ui.label(mode.name());
+                        ui.monospace(format!("{:.5}", value));
+                        ui.end_row();
+                    }
+                });
+            }
+            self.display_view(ui);
+            self.phase_plane_view(ui);
+
+            if let Some(edge) = self.selected_connection {
+                if let Some((from, to)) = self.connections.edge_endpoints(edge) {
+                    let (from_id, to_id) = (self.connections[from], self.connections[to]);
+                    ui.separator();
+                    ui.label(format!("Connection {} → {}", from_id, to_id));
+                    let mut gain = self.connections[edge].gain;
+                    ui.horizontal(|ui| {
+                        ui.label("Gain");
+                        if ui.add(egui::DragValue::new(&mut gain).speed(0.1)).changed() {
+                            self.set_connection_gain(edge, gain);
+                        }
+                        if ui
+                            .add_enabled(gain != 1.0, egui::Button::new("Reset"))
+                            .clicked()
+                        {
+                            self.set_connection_gain(edge, 1.0);
+                        }
+                    });
+                    if ui.button("Inject Disturbance").clicked() {
+                        self.inject_disturbance(edge);
+                    }
+                    let ports = self.components[&to_id].component_type.input_ports();
+                    if ports.len() > 1 {
+                        let mut connection = self.connections[edge];
+                        egui::ComboBox::from_label("Input port")
+                            .selected_text(ports.get(connection.to_port).copied().unwrap_or("?"))
+                            .show_ui(ui, |ui| {
+                                for (port, name) in ports.iter().enumerate() {
+                                    ui.selectable_value(&mut connection.to_port, port, *name);
+                                }
+                            });
+                        self.update_connection(edge, connection);
+                    }
+                }
+            }
+        });
+
+        if !self.validation.is_empty() {
+            egui::TopBottomPanel::bottom("validation_panel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.heading("Validation");
+                    if ui.small_button("Dismiss").clicked() {
+                        self.validation.clear();
+                    }
+                });
+                let mut select = None;
+                for error in &self.validation {
+                    let color = if error.blocks_run() {
+                        egui::Color32::RED
+                    } else {
+                        egui::Color32::YELLOW
+                    };
+                    let text = egui::RichText::new(error.to_string()).color(color);
+                    let label = egui::Label::new(text).sense(egui::Sense::click());
+                    if ui.add(label).clicked() {
+                        select = Some(error.components());
+                    }
+                }
+                if let Some(ids) = select {
+                    self.selected_components = ids.into_iter().collect();
+                    self.selected_connection = None;
+                }
+            });
+        }
+
+        if let Some(id) = self.selected_component() {
+            egui::SidePanel::right("parameter_panel").show(ctx, |ui| {
+                ui.heading("Parameters");
+                self.parameter_editor(ui, id);
+                match self.components.get(&id).map(|c| &c.component_type) {
+                    Some(ComponentType::PIDController { .. }) => {
+                        ui.separator();
+                        self.autotune_view(ui, id);
+                    }
+                    Some(ComponentType::TransferFunctionCoeffs { num, den }) => {
+                        ui.separator();
+                        self.pole_zero_view(ui, num, den, false);
+                    }
+                    Some(ComponentType::DiscreteTransferFunction { num, den }) => {
+                        ui.separator();
+                        self.pole_zero_view(ui, num, den, true);
+                    }
+                    _ => {}
+                }
+                self.sweep_view(ui, id);
+            });
+            if let Some(ComponentType::TransferFunctionCoeffs { num, den }) =
+                self.components.get(&id).map(|c| c.component_type.clone())
+            {
+                egui::TopBottomPanel::bottom("bode_panel").show(ctx, |ui| {
+                    self.bode_view(ui, &num, &den);
+                });
+            }
+        }
+
+     
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let pointer_pos = ui.input().pointer.hover_pos();
+            if ui.rect_contains_pointer(ui.max_rect()) {
+                let input = ui.input();
+                if input.pointer.middle_down() {
+                    self.camera.pan += input.pointer.delta();
+                }
+                if let (Some(pos), true) = (pointer_pos, input.scroll_delta.y != 0.0) {
+                    self.camera
+                        .zoom_around(pos, (input.scroll_delta.y * 0.002).exp());
+                }
+            }
+            if std::mem::take(&mut self.fit_pending) {
+                self.fit_to_content(ui.max_rect());
+            }
+            if let Some(component) = self
+                .focus_pending
+                .take()
+                .and_then(|id| self.components.get(&id))
+            {
+                let target = component.rect().center();
+                self.camera.center_on(target, ui.max_rect());
+            }
+            let camera = self.camera;
+
+            let painter = ui.painter();
+            let grid = self.snap_to_grid.then_some(self.grid_size);
+            if let Some(grid_size) = grid {
+                draw_grid(painter, camera, grid_size);
+            }
+            let mut connection_to_create = None; 
+            let pointer_world = pointer_pos.map(|pos| camera.to_world(pos));
+            let clicked = ui.input().pointer.any_click();
+            let secondary_clicked = ui.input().pointer.secondary_clicked();
+            let mut connection_to_remove = None;
+            let hovered_port =
+                pointer_world.and_then(|pos| self.port_at(pos, (PORT_RADIUS + 2.0) / camera.zoom));
+            let flagged: HashSet<usize> = self
+                .validation
+                .iter()
+                .flat_map(ValidationError::components)
+                .collect();
+            let draw_order = self.draw_order();
+            let under_pointer = pointer_world.and_then(|pos| {
+                draw_order
+                    .iter()
+                    .rev()
+                    .copied()
+                    .find(|id| self.components[id].rect().contains(pos))
+            });
+            let over_component = hovered_port.is_some() || under_pointer.is_some();
+            let (pressed, released, shift) = {
+                let input = ui.input();
+                let pressed = input.pointer.any_pressed() && input.pointer.primary_down();
+                (pressed, input.pointer.any_released(), input.modifiers.shift)
+            };
+            let over_menu = self
+                .context_menu
+                .as_ref()
+                .zip(pointer_pos)
+                .is_some_and(|(menu, pos)| menu.rect.contains(pos));
+            let in_canvas = ui.rect_contains_pointer(ui.max_rect()) && !over_menu;
+            let clicked = clicked && in_canvas;
+            if secondary_clicked && in_canvas && hovered_port.is_none() {
+                let menu_at = under_pointer.zip(pointer_pos);
+                self.context_menu = menu_at.map(|(component, pos)| ContextMenu {
+                    component,
+                    pos,
+                    rect: egui::Rect::NOTHING,
+                });
+            } else if clicked || ui.input().key_pressed(egui::Key::Escape) {
+                self.context_menu = None;
+            }
+            if let (Some(component_type), Some(pos), true) =
+                (block_shortcut.take(), pointer_world, in_canvas)
+            {
+                let position = self.cascade_position(pos);
+                self.add_component(component_type, position);
+            }
+            if let Some(component_type) = palette_pick.take() {
+                let position = self.cascade_position(camera.to_world(ui.max_rect().center()));
+                self.add_palette_block(component_type, position);
+            }
+            if self.palette_drag.is_some() && released {
+                let dropped = self.palette_drag.take();
+                if let (Some(component_type), Some(pos), true) = (dropped, pointer_world, in_canvas)
+                {
+                    self.add_palette_block(component_type, pos);
+                }
+            }
+
+            if clicked && !over_component {
+                if !shift {
+                    self.selected_components.clear();
+                }
+                self.selected_connection = None;
+                self.pending_port = None;
+            }
+            if pressed && in_canvas && hovered_port.is_none() {
+                match (under_pointer, pointer_world) {
+                    (Some(id), Some(pos)) => {
+                        if !self.selected_components.contains(&id) {
+                            if !shift {
+                                self.selected_components.clear();
+                            }
+                            self.selected_components.insert(id);
+                            self.selected_connection = None;
+                        }
+                        let positions = self
+                            .selected_components
+                            .iter()
+                            .filter_map(|id| {
+                                let component = self.components.get(id)?;
+                                Some((*id, component.position.clone().into()))
+                            })
+                            .collect();
+                        self.drag_origin = Some((pos, positions));
+                    }
+                    (None, Some(pos)) => self.rubber_band = Some(pos),
+                    _ => {}
+                }
+            }
+            if clicked && !shift && hovered_port.is_none() {
+                if let Some(id) = under_pointer {
+                    self.selected_components = HashSet::from([id]);
+                    self.selected_connection = None;
+                }
+            }
+            let double_clicked = ui
+                .input()
+                .pointer
+                .button_double_clicked(egui::PointerButton::Primary);
+            let subsystem_to_enter = under_pointer.filter(|id| {
+                double_clicked
+                    && in_canvas
+                    && matches!(
+                        self.components[id].component_type,
+                        ComponentType::Subsystem { .. }
+                    )
+            });
+
+            if let (Some((origin, positions)), Some(pos)) = (&self.drag_origin, pointer_world) {
+                let offset = pos - *origin;
+                for (id, start) in positions {
+                    if let Some(component) = self.components.get_mut(id) {
+                        let moved = *start + offset;
+                        component.position = match grid {
+                            Some(grid_size) => egui::pos2(
+                                (moved.x / grid_size).round() * grid_size,
+                                (moved.y / grid_size).round() * grid_size,
+                            )
+                            .into(),
+                            None => moved.into(),
+                        };
+                    }
+                }
+            }
+            if let (Some(start), Some(pos)) = (self.rubber_band, pointer_world) {
+                let band = egui::Rect::from_two_pos(start, pos);
+                if released {
+                    if !shift {
+                        self.selected_components.clear();
+                    }
+                    self.selected_components.extend(
+                        self.components
+                            .values()
+                            .filter(|c| c.rect().intersects(band))
+                            .map(|c| c.id),
+                    );
+                } else {
+                    painter.rect(
+                        camera.rect_to_screen(band),
+                        0.0,
+                        egui::Color32::from_rgba_unmultiplied(80, 160, 255, 30),
+                        (1.0, egui::Color32::from_rgb(80, 160, 255)),
+                    );
+                }
+            }
+            if released {
+                self.drag_origin = None;
+                self.rubber_band = None;
+            }
+
+        
+            let value_scale = self
+                .edge_values
+                .values()
+                .fold(0.0_f32, |scale, value| scale.max(value.abs()));
+            for edge in self.connections.edge_indices() {
+                let (from, to) = self.connections.edge_endpoints(edge).unwrap();
+                let connection = self.connections[edge];
+                let (source, target) = (
+                    &self.components[&self.connections[from]],
+                    &self.components[&self.connections[to]],
+                );
+                let route: Vec<Pos2> = wire_route(
+                    source.port_pos(PortKind::Output, connection.from_port),
+                    target.port_pos(PortKind::Input, connection.to_port),
+                    source.rect(),
+                    target.rect(),
+                )
+                .into_iter()
+                .map(|point| camera.to_screen(point))
+                .collect();
+                if clicked && !over_component {
+                    if let Some(pos) = pointer_pos {
+                        let near = route
+                            .windows(2)
+                            .any(|segment| distance_to_segment(pos, segment[0], segment[1]) < 5.0);
+                        if near {
+                            if secondary_clicked {
+                                connection_to_remove = Some(edge);
+                            } else if self.probe_mode {
+                                self.toggle_probe(edge);
+                            } else {
+                                self.selected_connection = Some(edge);
+                            }
+                        }
+                    }
+                }
+                let stroke = if self.selected_connection == Some(edge) {
+                    (2.5, egui::Color32::GOLD)
+                } else if let Some(value) = self.edge_values.get(&edge) {
+                    (1.5, signal_color(*value, value_scale))
+                } else {
+                    (1.0, egui::Color32::LIGHT_GRAY)
+                };
+                let (before_tip, tip) = (route[route.len() - 2], route[route.len() - 1]);
+                let probed = self
+                    .wire_ports(edge)
+                    .is_some_and(|wire| self.probes.contains(&wire));
+                let middle = route.len() / 2;
+                let midpoint = route[middle - 1] + (route[middle] - route[middle - 1]) / 2.0;
+                if probed {
+                    painter.circle_filled(midpoint, 4.0 * camera.zoom, PROBE_COLOR);
+                }
+                // Unity gain is the default and stays unlabelled.
+                if connection.gain != 1.0 {
+                    painter.text(
+                        midpoint + egui::vec2(5.0, -5.0) * camera.zoom,
+                        egui::Align2::LEFT_BOTTOM,
+                        format!("×{}", connection.gain),
+                        FontId::proportional(11.0 * camera.zoom),
+                        stroke.1,
+                    );
+                }
+                painter.add(egui::Shape::line(route, stroke));
+                draw_arrowhead(painter, before_tip, tip, camera.zoom, stroke.1);
+            }
+            if !self.edge_values.is_empty() {
+                draw_colorbar(painter, ui.max_rect(), value_scale);
+            }
+
+        
+            for id in &draw_order {
+                let component = &self.components[id];
+                let pos = camera.to_screen(component.position.clone().into());
+                let rect = camera.rect_to_screen(component.rect());
+
+                let selected = self.selected_components.contains(id);
+                let outline = if selected {
+                    Some((3.0, egui::Color32::GOLD))
+                } else if flagged.contains(id) {
+                    Some((2.0, egui::Color32::RED))
+                } else if under_pointer == Some(*id) && hovered_port.is_none() {
+                    Some((1.5, egui::Color32::from_rgb(120, 180, 255)))
+                } else {
+                    None
+                };
+                if selected {
+                    let halo = (4.0, egui::Color32::from_rgba_unmultiplied(255, 215, 0, 60));
+                    if component.injection_point {
+                        painter.circle_stroke(pos, rect.width() / 2.0 + 4.0, halo);
+                    } else {
+                        painter.rect_stroke(rect.expand(4.0), 7.0 * camera.zoom, halo);
+                    }
+                }
+                let (fill, text_color) = if component.enabled {
+                    (component.component_type.color(), egui::Color32::BLACK)
+                } else {
+                    (egui::Color32::from_gray(90), egui::Color32::from_gray(160))
+                };
+                if component.injection_point {
+                    let radius = rect.width() / 2.0;
+                    painter.circle_filled(pos, radius, fill);
+                    if let Some(stroke) = outline {
+                        painter.circle_stroke(pos, radius, stroke);
+                    }
+                    painter.text(
+                        pos,
+                        egui::Align2::CENTER_CENTER,
+                        "+",
+                        FontId::proportional(14.0 * camera.zoom),
+                        text_color,
+                    );
+                    painter.text(
+                        rect.right_top(),
+                        egui::Align2::LEFT_BOTTOM,
+                        component.name.as_deref().unwrap_or("d"),
+                        FontId::proportional(11.0 * camera.zoom),
+                        egui::Color32::LIGHT_GRAY,
+                    );
+                } else {
+                    painter.rect_filled(rect, 5.0 * camera.zoom, fill);
+                    if let Some(stroke) = outline {
+                        painter.rect_stroke(rect, 5.0 * camera.zoom, stroke);
+                    }
+                    painter.text(
+                        pos,
+                        egui::Align2::CENTER_CENTER,
+                        component.label(),
+                        FontId::proportional(14.0 * camera.zoom),
+                        text_color,
+                    );
+                    if self.unconnected_sink(*id) {
+                        let badge = rect.right_top();
+                        painter.circle_filled(badge, 7.0 * camera.zoom, egui::Color32::YELLOW);
+                        painter.text(
+                            badge,
+                            egui::Align2::CENTER_CENTER,
+                            "!",
+                            FontId::proportional(11.0 * camera.zoom),
+                            egui::Color32::BLACK,
+                        );
+                    }
+                }
+
+                for port in component.ports() {
+                    // While a wire is pending, inputs it could end on are marked.
+                    let target = self.pending_port.is_some_and(|start| {
+                        port.kind == PortKind::Input && start.component != port.component
+                    });
+                    let color = if self.pending_port == Some(port) {
+                        egui::Color32::GOLD
+                    } else if hovered_port == Some(port) {
+                        egui::Color32::WHITE
+                    } else if target {
+                        egui::Color32::LIGHT_GREEN
+                    } else {
+                        egui::Color32::DARK_GRAY
+                    };
+                    let radius = if hovered_port == Some(port) {
+                        PORT_RADIUS * 1.5
+                    } else {
+                        PORT_RADIUS
+                    };
+                    painter.circle_filled(
+                        camera.to_screen(component.port_pos(port.kind, port.index)),
+                        radius * camera.zoom,
+                        color,
+                    );
+                }
+            }
+
+            let tooltip_for = under_pointer
+                .filter(|_| hovered_port.is_none() && self.drag_origin.is_none())
+                .filter(|_| self.palette_drag.is_none())
+                .filter(|_| self.context_menu.is_none() && in_canvas);
+            if let Some(id) = tooltip_for {
+                let component = &self.components[&id];
+                let mut text = component.component_type.describe();
+                if let Some(name) = &component.name {
+                    text = format!("{}\n{}", name, text);
+                }
+                let tooltip_id = egui::Id::new(("block_tooltip", id));
+                egui::show_tooltip_at_pointer(ui.ctx(), tooltip_id, |ui| ui.label(text));
+            }
+
+            // Outline of the block being dragged from the palette, drawn above
+            // the panels so it follows the pointer out of the palette.
+            if let (Some(component_type), Some(pos)) = (&self.palette_drag, pointer_pos) {
+                let layer = egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("palette_drag"));
+                let ghost = ui.ctx().layer_painter(layer);
+                let rect = egui::Rect::from_center_size(pos, egui::vec2(80.0, 40.0) * camera.zoom);
+                let fill = component_type.color().linear_multiply(0.5);
+                ghost.rect_filled(rect, 5.0 * camera.zoom, fill);
+                ghost.text(
+                    pos,
+                    egui::Align2::CENTER_CENTER,
+                    component_type.label(),
+                    FontId::proportional(14.0 * camera.zoom),
+                    egui::Color32::BLACK,
+                );
+                ui.output().cursor_icon = egui::CursorIcon::Grabbing;
+            }
+
+            if clicked && !secondary_clicked {
+                if let Some(port) = hovered_port {
+                    match (self.pending_port.take(), port.kind) {
+                        (Some(start), PortKind::Input) => {
+                            connection_to_create = Some((start, port))
+                        }
+                        (_, PortKind::Output) => self.pending_port = Some(port),
+                        (None, PortKind::Input) => {
+                            self.last_error = Some("Start a connection at an output port".into());
+                        }
+                    }
+                }
+            }
+            if ui.input().key_pressed(egui::Key::Escape) {
+                self.pending_port = None;
+            }
+            if let (Some(start), Some(pos)) = (self.pending_port, pointer_pos) {
+                if let Some(component) = self.components.get(&start.component) {
+                    let from_pos = camera.to_screen(component.port_pos(start.kind, start.index));
+                    painter.line_segment([from_pos, pos], (1.5, egui::Color32::GOLD));
+                }
+            }
+
+         
+            if let Some((output, input)) = connection_to_create {
+                self.last_error = self
+                    .connect_components(
+                        output.component,
+                        output.index,
+                        input.component,
+                        input.index,
+                    )
+                    .err()
+                    .map(str::to_string);
+            }
+
+            if ui.input().key_pressed(egui::Key::Delete) {
+                if !self.selected_components.is_empty() {
+                    let mut ids: Vec<usize> = self.selected_components.drain().collect();
+                    ids.sort();
+                    for id in ids {
+                        self.remove_component(id);
+                    }
+                } else if let Some(edge) = self.selected_connection {
+                    connection_to_remove = Some(edge);
+                }
+            }
+
+            if let Some(edge) = connection_to_remove {
+                self.disconnect(edge);
+            }
+            if let Some(id) = subsystem_to_enter {
+                self.enter_subsystem(id);
+            }
+        });
+
+        self.context_menu_view(ctx);
+    }
+}
+
+// Binary models start with a tag and a format version. Bincode stores enum
+// variants by index and struct fields by position, so a file from another
+// layout would otherwise decode as the wrong blocks; bump BIN_VERSION
+// whenever anything reachable from ModelFile changes shape.
+const BIN_MAGIC: &[u8; 4] = b"SIMU";
+const BIN_VERSION: u32 = 2;
+
+fn encode_bin(model: &ModelFile) -> io::Result<Vec<u8>> {
+    let mut bytes = BIN_MAGIC.to_vec();
+    bytes.extend(BIN_VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, model)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(bytes)
+}
+
+fn decode_bin(bytes: &[u8]) -> io::Result<ModelFile> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let payload = bytes
+        .strip_prefix(BIN_MAGIC)
+        .ok_or_else(|| invalid("not a binary model, or one saved before versioning".into()))?;
+    let (version, payload) = match payload {
+        [a, b, c, d, rest @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]), rest),
+        _ => return Err(invalid("binary model is truncated".into())),
+    };
+    if version != BIN_VERSION {
+        return Err(invalid(format!(
+            "binary model format {} is not supported (expected {})",
+            version, BIN_VERSION
+        )));
+    }
+    bincode::deserialize(payload).map_err(|err| invalid(err.to_string()))
+}
+
+fn is_binary_model(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "bin")
+}
+
+// Calendar date (UTC) of a Unix timestamp, as YYYY-MM-DD.
+fn format_date(unix_secs: u64) -> String {
+    // Civil-from-days, counting eras of 400 years from 0000-03-01.
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+const USAGE: &str = "usage: simu [--run MODEL.json [--out RESULTS.csv] [--svg DIAGRAM.svg] \
+                     [--dt SECONDS] [--duration SECONDS] [--log]]";
+
+fn run_headless(args: &[String]) -> Result<(), String> {
+    let mut app = SimulatorApp::new();
+    let mut model = None;
+    let mut out = None;
+    let mut svg = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--run" => model = Some(value()?),
+            "--out" => out = Some(value()?),
+            "--svg" => svg = Some(value()?),
+            "--log" => app.logging = true,
+            "--dt" => {
+                app.dt = value()?
+                    .parse()
+                    .map_err(|err| format!("invalid --dt: {}", err))?
+            }
+            "--duration" => {
+                app.total_time = value()?
+                    .parse()
+                    .map_err(|err| format!("invalid --duration: {}", err))?
+            }
+            other => return Err(format!("unexpected argument `{}`\n{}", other, USAGE)),
+        }
+    }
+    let model = model.ok_or(USAGE)?;
+    if out.is_none() && svg.is_none() {
+        return Err(USAGE.to_string());
+    }
+
+    app.load_from_path(Path::new(model))
+        .map_err(|err| format!("failed to load {}: {}", model, err))?;
+    if let Some(svg) = svg {
+        app.export_svg(Path::new(svg))
+            .map_err(|err| format!("failed to write {}: {}", svg, err))?;
+    }
+    let out = match out {
+        Some(out) => out,
+        None => return Ok(()),
+    };
+    let errors = app.validate();
+    for error in &errors {
+        eprintln!("simu: {}", error);
+    }
+    if errors.iter().any(ValidationError::blocks_run) {
+        return Err("model failed validation".to_string());
+    }
+    app.simulate().map_err(|err| err.to_string())?;
+    app.export_csv(Path::new(out))
+        .map_err(|err| format!("failed to write {}: {}", out, err))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(err) = run_headless(&args) {
+            eprintln!("simu: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let options = NativeOptions::default();
+    eframe::run_native(
+        " Simulator",
+        options,
+        Box::new(|_cc| Box::new(SimulatorApp::new())),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A chain of `n` gains fed by a sine into a scope.
+    fn chain_model(n: usize) -> SimulatorApp {
+        let mut app = SimulatorApp::new();
+        let sine = ComponentType::Sine {
+            amplitude: 1.0,
+            freq_hz: 1.0,
+            phase: 0.0,
+        };
+        app.add_component(sine, Pos2::new(0.0, 0.0));
+        for i in 1..=n {
+            app.add_component(ComponentType::Gain { k: 1.0 }, Pos2::new(i as f32, 0.0));
+            app.connect_components(i - 1, 0, i, 0).unwrap();
+        }
+        app.add_component(ComponentType::Scope, Pos2::new(0.0, 50.0));
+        app.connect_components(n, 0, n + 1, 0).unwrap();
+        app
+    }
+
+    fn round_trip(extension: &str) {
+        let app = chain_model(3);
+        let path = std::env::temp_dir().join(format!(
+            "simu-round-trip-{}.{}",
+            std::process::id(),
+            extension
+        ));
+        app.save_to_path(&path).unwrap();
+        let mut loaded = SimulatorApp::new();
+        loaded.load_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let (saved, loaded) = (app.root_model(), loaded.root_model());
+        assert_eq!(
+            serde_json::to_string(&saved).unwrap(),
+            serde_json::to_string(&loaded).unwrap()
+        );
+    }
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    // Largest error of integrating sin(t) over 10 s against 1 - cos(t).
+    fn integration_error(solver: SolverMethod) -> f32 {
+        let dt = 0.1;
+        let (mut accumulator, mut prev_input) = (0.0, None);
+        let mut worst: f32 = 0.0;
+        for step in 0..100 {
+            let t = step as f32 * dt;
+            worst = worst.max((accumulator - (1.0 - t.cos())).abs());
+            integrate(&mut accumulator, &mut prev_input, t.sin(), dt, solver);
+        }
+        worst
+    }
+
+    #[test]
+    fn rk4_integrates_sine_more_accurately_than_euler() {
+        let euler = integration_error(SolverMethod::Euler);
+        let rk4 = integration_error(SolverMethod::Rk4);
+        assert_close(euler, 0.051, 0.005);
+        assert!(rk4 < 0.01, "RK4 error {}", rk4);
+        assert!(rk4 * 5.0 < euler, "RK4 {} vs Euler {}", rk4, euler);
+    }
+
+    #[test]
+    fn tustin_integrator_is_trapezoidal() {
+        let (b, a) = tustin(&[1.0], &[1.0, 0.0], 0.1);
+        assert_eq!(a.len(), 2);
+        assert_close(a[0], 1.0, 1e-6);
+        assert_close(a[1], -1.0, 1e-6);
+        assert_close(b[0], 0.05, 1e-6);
+        assert_close(b[1], 0.05, 1e-6);
+    }
+
+    #[test]
+    fn z_domain_pads_and_normalizes() {
+        assert_eq!(
+            z_domain(&[1.0], &[2.0, -1.0]),
+            (vec![0.0, 0.5], vec![1.0, -0.5])
+        );
+        assert_eq!(z_domain(&[1.0], &[0.0, 1.0]), (vec![0.0], vec![1.0]));
+    }
+
+    #[test]
+    fn lookup_interpolates_and_holds_ends() {
+        let (breakpoints, values) = ([0.0, 1.0, 2.0], [0.0, 10.0, 0.0]);
+        assert_close(lookup(&breakpoints, &values, 0.5), 5.0, 1e-6);
+        assert_close(lookup(&breakpoints, &values, 1.25), 7.5, 1e-6);
+        assert_eq!(lookup(&breakpoints, &values, -1.0), 0.0);
+        assert_eq!(lookup(&breakpoints, &values, 3.0), 0.0);
+        assert_eq!(lookup(&[], &[], 1.0), 0.0);
+    }
+
+    #[test]
+    fn step_metrics_of_first_order_response() {
+        let dt = 0.01;
+        let data: Vec<f32> = (0..1000).map(|i| 1.0 - (-(i as f32) * dt).exp()).collect();
+        let metrics = step_metrics(&data, dt).unwrap();
+        assert_close(metrics.rise_time.unwrap(), 9f32.ln(), 0.02);
+        assert_close(metrics.overshoot_percent, 0.0, 0.1);
+        assert_close(metrics.settling_time.unwrap(), 50f32.ln(), 0.02);
+        assert!(step_metrics(&[1.0; 10], dt).is_none());
+    }
+
+    #[test]
+    fn poly_roots_finds_real_and_complex_roots() {
+        let mut roots: Vec<f64> = poly_roots(&[1.0, -3.0, 2.0]).iter().map(|r| r.re).collect();
+        roots.sort_by(f64::total_cmp);
+        assert!((roots[0] - 1.0).abs() < 1e-9 && (roots[1] - 2.0).abs() < 1e-9);
+        let roots = poly_roots(&[0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(roots.len(), 2);
+        for root in roots {
+            assert!(root.re.abs() < 1e-9 && (root.im.abs() - 1.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn large_outputs_only_diverge_with_a_bound() {
+        let mut app = SimulatorApp::new();
+        let ramp = ComponentType::Ramp {
+            slope: 1e8,
+            start_time: 0.0,
+        };
+        app.add_component(ramp, Pos2::new(0.0, 0.0));
+        app.add_component(ComponentType::Scope, Pos2::new(100.0, 0.0));
+        app.connect_components(0, 0, 1, 0).unwrap();
+        app.dt = 0.1;
+        app.total_time = 1.0;
+        assert_eq!(app.simulate(), Ok(()));
+        app.divergence_bound = Some(1e6);
+        assert!(matches!(
+            app.simulate(),
+            Err(SimError::Diverged { component: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn divergence_is_checked_on_every_output() {
+        let mut outputs = HashMap::new();
+        outputs.insert((0, 0), Signal::Vector(vec![1.0, f32::NAN]));
+        assert!(check_divergence(&outputs, 0, 1, 0, None).is_err());
+        outputs.insert((0, 0), Signal::Scalar(1.0));
+        outputs.insert((0, 1), Signal::Scalar(f32::INFINITY));
+        assert!(check_divergence(&outputs, 0, 1, 0, None).is_ok());
+        assert!(check_divergence(&outputs, 0, 2, 0, None).is_err());
+    }
+
+    #[test]
+    fn transport_delay_buffer_is_capped_at_the_run_length() {
+        let delay = ComponentType::TransportDelay { seconds: 1e9 };
+        let ComponentState::TransportDelay { buffer, samples } =
+            ComponentState::new(&delay, 0.01, SolverMethod::Euler, 1.0)
+        else {
+            panic!("not a transport delay state");
+        };
+        assert_eq!(samples, 101.0);
+        assert_eq!(buffer.len(), 103);
+        let delay = ComponentType::TransportDelay { seconds: 0.25 };
+        let ComponentState::TransportDelay { samples, .. } =
+            ComponentState::new(&delay, 0.01, SolverMethod::Euler, 1.0)
+        else {
+            panic!("not a transport delay state");
+        };
+        assert_close(samples, 25.0, 1e-3);
+    }
+
+    // The model as sorted JSON lines, independent of edge order.
+    fn model_lines(app: &SimulatorApp) -> Vec<String> {
+        let model = app.to_model();
+        let mut lines: Vec<String> = model
+            .components
+            .iter()
+            .map(|component| serde_json::to_string(component).unwrap())
+            .chain(
+                model
+                    .connections
+                    .iter()
+                    .map(|record| serde_json::to_string(record).unwrap()),
+            )
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn disturbance_injection_is_one_undo_step() {
+        let mut app = chain_model(1);
+        let before = model_lines(&app);
+        let undo_depth = app.undo_stack.len();
+        app.inject_disturbance(EdgeIndex::new(0));
+        let injected = model_lines(&app);
+        assert_eq!(app.components.len(), 5);
+        assert_eq!(app.undo_stack.len(), undo_depth + 1);
+        app.undo();
+        assert_eq!(model_lines(&app), before);
+        app.redo();
+        assert_eq!(model_lines(&app), injected);
+    }
+
+    #[test]
+    fn wire_edits_undo_before_the_wire_itself() {
+        let mut app = SimulatorApp::new();
+        app.add_component(ComponentType::Step, Pos2::new(0.0, 0.0));
+        app.add_component(ComponentType::Scope, Pos2::new(100.0, 0.0));
+        app.connect_components(0, 0, 1, 0).unwrap();
+        let edge = EdgeIndex::new(0);
+        app.set_connection_gain(edge, 2.0);
+        app.set_connection_gain(edge, 3.0);
+        app.undo();
+        assert_eq!(app.connections[edge].gain, 1.0);
+        app.redo();
+        assert_eq!(app.connections[edge].gain, 3.0);
+        app.undo();
+        app.undo();
+        assert_eq!(app.connections.edge_count(), 0);
+    }
+
+    #[test]
+    fn narrowing_a_mux_drops_its_extra_wires_undoably() {
+        let mut app = SimulatorApp::new();
+        app.add_component(ComponentType::Mux { width: 3 }, Pos2::new(100.0, 0.0));
+        for port in 0..3 {
+            app.add_component(ComponentType::Step, Pos2::new(0.0, port as f32 * 50.0));
+            app.connect_components(port + 1, 0, 0, port).unwrap();
+        }
+        app.selected_connection = Some(EdgeIndex::new(2));
+        app.components.get_mut(&0).unwrap().component_type = ComponentType::Mux { width: 2 };
+        app.prune_port_edges(0);
+        assert_eq!(app.connections.edge_count(), 2);
+        assert_eq!(app.selected_connection, None);
+        app.components.get_mut(&0).unwrap().component_type = ComponentType::Mux { width: 3 };
+        app.undo();
+        assert_eq!(app.connections.edge_count(), 3);
+    }
+
+    #[test]
+    fn sweep_runs_in_the_background() {
+        let mut app = chain_model(1);
+        app.total_time = 0.5;
+        app.sweep_settings = SweepSettings {
+            parameter: 0,
+            from: 1.0,
+            to: 3.0,
+            step: 1.0,
+        };
+        app.start_sweep(1);
+        let started = Instant::now();
+        while app.sweep_run.is_some() && started.elapsed() < Duration::from_secs(30) {
+            app.poll_sweep();
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(app.sweep_run.is_none());
+        assert_eq!(app.last_error, None);
+        assert_eq!(app.sweep.len(), 3);
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("Scope 3"), "Scope 3");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn shortening_a_delay_during_a_run_takes_effect_at_once() {
+        let mut app = SimulatorApp::new();
+        let ramp = ComponentType::Ramp {
+            slope: 1.0,
+            start_time: 0.0,
+        };
+        let delay = ComponentType::Delay {
+            steps: 3,
+            initial: 0.0,
+        };
+        app.add_component(ramp, Pos2::new(0.0, 0.0));
+        app.add_component(delay, Pos2::new(100.0, 0.0));
+        app.add_component(ComponentType::Scope, Pos2::new(200.0, 0.0));
+        app.connect_components(0, 0, 1, 0).unwrap();
+        app.connect_components(1, 0, 2, 0).unwrap();
+        app.dt = 0.1;
+        let mut run = app.start_run().unwrap();
+        for _ in 0..5 {
+            app.advance(&mut run).unwrap();
+        }
+        assert_close(app.simulation_data[&2][4], 0.1, 1e-6);
+        app.components.get_mut(&1).unwrap().component_type = ComponentType::Delay {
+            steps: 1,
+            initial: 0.0,
+        };
+        app.advance(&mut run).unwrap();
+        app.advance(&mut run).unwrap();
+        assert_close(app.simulation_data[&2][5], 0.4, 1e-6);
+        assert_close(app.simulation_data[&2][6], 0.5, 1e-6);
+    }
+
+    #[test]
+    fn integrator_reset_zeroes_the_accumulator() {
+        let mut app = SimulatorApp::new();
+        let integrator = ComponentType::IntegratorIC {
+            initial: 5.0,
+            reset: Some(0.5),
+        };
+        let reset = ComponentType::Pulse {
+            amplitude: 1.0,
+            period: 1.0,
+            duty: 0.5,
+        };
+        app.add_component(ComponentType::Constant { value: 1.0 }, Pos2::new(0.0, 0.0));
+        app.add_component(reset, Pos2::new(0.0, 100.0));
+        app.add_component(integrator, Pos2::new(100.0, 0.0));
+        app.add_component(ComponentType::Scope, Pos2::new(200.0, 0.0));
+        app.connect_components(0, 0, 2, 0).unwrap();
+        app.connect_components(1, 0, 2, 1).unwrap();
+        app.connect_components(2, 0, 3, 0).unwrap();
+        app.dt = 0.1;
+        app.total_time = 1.2;
+        app.simulate().unwrap();
+        let data = &app.simulation_data[&3];
+        // Counts up from 5 until the pulse rises again at t = 1.
+        assert_close(data[10], 6.0, 1e-4);
+        assert_close(data[11], 0.0, 1e-6);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip("json");
+    }
+
+    #[test]
+    fn bin_round_trip() {
+        round_trip("bin");
+    }
+
+    #[test]
+    fn old_delay_and_memory_shapes_still_load() {
+        let component = |component_type: &str| {
+            let json = format!(
+                r#"{{"id":0,"component_type":{},"position":{{"x":0.0,"y":0.0}},"is_dragging":false}}"#,
+                component_type
+            );
+            serde_json::from_str::<Component>(&json)
+                .unwrap()
+                .component_type
+        };
+        assert!(matches!(
+            component(r#"{"Delay":3}"#),
+            ComponentType::Delay { steps: 3, initial } if initial == 0.0
+        ));
+        assert!(matches!(
+            component(r#""Memory""#),
+            ComponentType::Memory { initial } if initial == 0.0
+        ));
+        let current = ComponentType::Delay {
+            steps: 2,
+            initial: 1.5,
+        };
+        let saved = serde_json::to_string(&current).unwrap();
+        assert_eq!(saved, r#"{"Delay":{"steps":2,"initial":1.5}}"#);
+        assert!(matches!(
+            component(&saved),
+            ComponentType::Delay { steps: 2, initial } if initial == 1.5
+        ));
+    }
+
+    #[test]
+    fn bin_rejects_other_versions() {
+        let mut bytes = encode_bin(&chain_model(1).to_model()).unwrap();
+        assert!(decode_bin(&bytes).is_ok());
+        bytes[4] += 1;
+        assert!(decode_bin(&bytes).is_err());
+        assert!(decode_bin(&bytes[4..]).is_err());
+    }
+
+    #[test]
+    fn bin_is_smaller_than_json_for_large_models() {
+        let model = chain_model(1000).to_model();
+        let json = serde_json::to_string(&model).unwrap();
+        let bin = encode_bin(&model).unwrap();
+        assert!(
+            bin.len() < json.len(),
+            "bincode {} bytes, JSON {} bytes",
+            bin.len(),
+            json.len()
+        );
+        let decoded = serde_json::to_string(&decode_bin(&bin).unwrap()).unwrap();
+        assert_eq!(decoded, json);
+    }
 }